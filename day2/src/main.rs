@@ -1,92 +1,96 @@
-use std::io::BufReader;
-use std::io::BufRead;
-use std::io;
-use std::fs;
+mod symbolic;
 
-fn file_to_vec(filename: String) -> io::Result<Vec<i32>> {
-    let file_in = fs::File::open(filename)?;
-    let file_reader = BufReader::new(file_in);
-    let rows : Vec<String> = file_reader.lines().filter_map(io::Result::ok).collect();
-    let v: Vec<i32> = rows[0].split(",").map(|x| x.parse::<i32>().expect("parse error")).collect();
+use intcode::{read_program, Error, Machine};
 
-    return Ok(v);
-}
+// Some noun/verb pairs turn the program into an endless loop.
+const STEP_BUDGET: u64 = 100_000;
 
-fn run_program(program: &mut Vec<i32>) {
-    let mut index = 0;
-    let mut done = false;
-    
-    while !done {
-        let opcode = program[index];
-        if opcode == 1 || opcode == 2 {
-            let idx1 = program[index + 1];
-            let idx2 = program[index + 2];
-            let idx3 = program[index + 3];
-            program[idx3 as usize] = 
-                if opcode == 1 {
-                    program[idx1 as usize] + program[idx2 as usize]
-                }
-                else {
-                    program[idx1 as usize] * program[idx2 as usize]
-                };
-            index += 4;
-        } 
-        else {
-            done = true;
-        }
-    }
-} 
+const TARGET: i64 = 19690720;
 
-fn start_program(program: &Vec<i32>, noun: i32, verb: i32)-> i32 {
-    let mut result = program.clone();
-    result[1] = noun;
-    result[2] = verb;
-    run_program(&mut result);
-    return result[0];
+fn start_program(program: &[i64], noun: i64, verb: i64)-> Result<i64, Error> {
+    let mut machine = Machine::new(program);
+    machine.write(1, noun).expect("address 1 is writable");
+    machine.write(2, verb).expect("address 2 is writable");
+    machine.set_budget(Some(STEP_BUDGET));
+    machine.detect_repeats();
+    machine.run()?;
+    Ok(machine.read(0))
 }
 
 
-fn task2(program: &Vec<i32>) -> i32 {
-    for noun in 0..99{
-        for verb in 0..99{
-            if start_program(program, noun, verb) == 19690720 {
+fn search(program: &[i64], target: i64) -> i64 {
+    for noun in 0..=99 {
+        for verb in 0..=99 {
+            if start_program(program, noun, verb) == Ok(target) {
                 return noun * 100 + verb;
             }
         }
     }
 
-    return -1;
+    -1
+}
+
+fn task2(program: &[i64]) -> i64 {
+    match symbolic::solve(program, TARGET) {
+        Ok((noun, verb)) => noun * 100 + verb,
+        Err(e) => {
+            println!("Solving task 2 failed, searching instead: {}", e);
+            search(program, TARGET)
+        }
+    }
 }
 
+fn main() {
+    let row = read_program("data.txt");
+    match row {
+    Ok(numbers) => {
+        match start_program(&numbers,12, 2) {
+            Ok(answer1) => println!("Task 1: {:?}", answer1),
+            Err(e) => println!("Error running program: {}", e)
+        }
+        let answer2 = task2(&numbers);
+        println!("Task 2: {:?}", answer2);
+    },
+    Err(e) => println!("Error reading file: {}", e)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use intcode::ErrorKind;
 
     #[test]
     fn test_run_program() {
-        let mut program = vec![2,4,4,5,99,0];
+        let mut machine = Machine::new(&[2,4,4,5,99,0]);
         let expected = vec![2,4,4,5,99,9801];
-        run_program(&mut program);
-        assert_eq!(program, expected);
+        machine.run().unwrap();
+        assert_eq!(machine.memory(), expected);
     }
 
     #[test]
     fn test_start_program(){
         let program = vec![1,0,0,4,99,5,6,0,99];
-        assert_eq!(start_program(&program, 1, 1), 30);
+        assert_eq!(start_program(&program, 1, 1), Ok(30));
     }
-}
 
-fn main() {
-    let row = file_to_vec("data.txt".to_string());
-    match row {
-    Ok(numbers) => {
-        let answer1 = start_program(&numbers,12, 2);
-        println!("Task 1: {:?}", answer1);
-        let answer2 = task2(&numbers);
-        println!("Task 2: {:?}", answer2);
-    },
-    Err(e) => println!("Error reading file: {:?}", e)
-    }   
+    #[test]
+    fn test_search_and_solve_agree(){
+        // noun * 100 + verb, after the usual first instruction
+        let program = vec![1,0,0,3,2,1,13,0,1,0,2,0,99,100];
+        assert_eq!(search(&program, 9999), 9999);
+        assert_eq!(symbolic::solve(&program, 9999), Ok((99, 99)));
+        let program = read_program("data.txt").unwrap();
+        assert_eq!(task2(&program), search(&program, TARGET));
+    }
+
+    #[test]
+    fn test_start_program_never_halts(){
+        // noun 1, verb 0 makes the first instruction jump to itself
+        let program = vec![1105,0,0,99];
+        assert_eq!(start_program(&program, 1, 0).unwrap_err().kind, ErrorKind::Repeats);
+        // while this one counts upwards forever
+        let program = vec![1001,0,0,8,1105,1,0,99,0];
+        assert_eq!(start_program(&program, 8, 1).unwrap_err().kind, ErrorKind::BudgetExhausted);
+    }
 }
@@ -0,0 +1,350 @@
+//! Runs the day2 program with noun and verb left as unknowns, building an
+//! expression for memory cell 0, so the target can be solved for instead of
+//! searched for.
+
+use std::fmt;
+use std::rc::Rc;
+
+/// How many nodes the expression for a cell may have, counting shared
+/// subexpressions once for every place they appear. A program that keeps
+/// adding a cell to itself doubles it each time.
+pub const MAX_NODES: usize = 10_000;
+
+/// Subexpressions are shared, so copying a cell costs nothing however large
+/// its expression is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(i64),
+    Noun,
+    Verb,
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+}
+
+/// `self.noun * noun + self.verb * verb + self.constant`, the form every
+/// linear expression simplifies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Linear {
+    pub noun: i64,
+    pub verb: i64,
+    pub constant: i64,
+}
+
+impl Linear {
+    fn is_constant(&self) -> bool {
+        self.noun == 0 && self.verb == 0
+    }
+
+    fn scale(self, factor: i64) -> Result<Linear, SolveError> {
+        let scale = |value: i64| value.checked_mul(factor).ok_or(SolveError::Overflow);
+        Ok(Linear {
+            noun: scale(self.noun)?,
+            verb: scale(self.verb)?,
+            constant: scale(self.constant)?,
+        })
+    }
+
+    fn add(self, other: Linear) -> Result<Linear, SolveError> {
+        let add = |a: i64, b| a.checked_add(b).ok_or(SolveError::Overflow);
+        Ok(Linear {
+            noun: add(self.noun, other.noun)?,
+            verb: add(self.verb, other.verb)?,
+            constant: add(self.constant, other.constant)?,
+        })
+    }
+}
+
+impl Expr {
+    /// `a + b`, folding constants.
+    fn add(a: Rc<Expr>, b: Rc<Expr>) -> Result<Rc<Expr>, SolveError> {
+        Ok(match (&*a, &*b) {
+            (Expr::Const(x), Expr::Const(y)) => Rc::new(Expr::Const(x.checked_add(*y).ok_or(SolveError::Overflow)?)),
+            (Expr::Const(0), _) => b,
+            (_, Expr::Const(0)) => a,
+            _ => Rc::new(Expr::Add(a, b)),
+        })
+    }
+
+    /// `a * b`, folding constants.
+    fn mul(a: Rc<Expr>, b: Rc<Expr>) -> Result<Rc<Expr>, SolveError> {
+        Ok(match (&*a, &*b) {
+            (Expr::Const(x), Expr::Const(y)) => Rc::new(Expr::Const(x.checked_mul(*y).ok_or(SolveError::Overflow)?)),
+            (Expr::Const(1), _) => b,
+            (_, Expr::Const(1)) => a,
+            _ => Rc::new(Expr::Mul(a, b)),
+        })
+    }
+
+    /// Number of nodes, counting a shared subexpression every time it
+    /// appears.
+    pub fn nodes(&self) -> usize {
+        match self {
+            Expr::Add(a, b) | Expr::Mul(a, b) => 1 + a.nodes() + b.nodes(),
+            _ => 1,
+        }
+    }
+
+    /// The expression as `a * noun + b * verb + c`, or `None` if it
+    /// multiplies noun or verb by something that is not a constant.
+    /// Fails with [`SolveError::Overflow`] if `a`, `b` or `c` do not fit
+    /// in an `i64`.
+    pub fn linear(&self) -> Result<Option<Linear>, SolveError> {
+        Ok(match self {
+            Expr::Const(value) => Some(Linear { noun: 0, verb: 0, constant: *value }),
+            Expr::Noun => Some(Linear { noun: 1, verb: 0, constant: 0 }),
+            Expr::Verb => Some(Linear { noun: 0, verb: 1, constant: 0 }),
+            Expr::Add(a, b) => match (a.linear()?, b.linear()?) {
+                (Some(a), Some(b)) => Some(a.add(b)?),
+                _ => None,
+            },
+            Expr::Mul(a, b) => match (a.linear()?, b.linear()?) {
+                (Some(a), Some(b)) if a.is_constant() => Some(b.scale(a.constant)?),
+                (Some(a), Some(b)) if b.is_constant() => Some(a.scale(b.constant)?),
+                _ => None,
+            },
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Noun => write!(f, "noun"),
+            Expr::Verb => write!(f, "verb"),
+            Expr::Add(a, b) => write!(f, "{} + {}", a, b),
+            Expr::Mul(a, b) => {
+                for (i, factor) in [a, b].iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " * ")?;
+                    }
+                    match ***factor {
+                        Expr::Add(..) => write!(f, "({})", factor)?,
+                        _ => write!(f, "{}", factor)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveError {
+    /// The instruction at this index, or the address it writes to, depends
+    /// on noun or verb, or it read through such an address and what it read
+    /// ends up in cell 0.
+    SymbolicAddress(usize),
+    /// The instruction at this index is not add, multiply or halt, or it
+    /// uses an address outside the program.
+    Unsupported(usize),
+    /// Cell 0 does not depend on noun and verb linearly.
+    NotInvertible(Expr),
+    /// No noun and verb in `0..=99` give the target.
+    NoSolution(i64),
+    /// Folding constants, working out the coefficients of cell 0, or noun
+    /// and verb from them, overflows an `i64`.
+    Overflow,
+    /// The instruction at this index makes an expression with more than
+    /// [`MAX_NODES`] nodes.
+    TooLarge(usize),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::SymbolicAddress(index) => {
+                write!(f, "the instruction at {} uses an address that depends on noun or verb", index)
+            }
+            SolveError::Unsupported(index) => write!(f, "cannot run the instruction at {} symbolically", index),
+            SolveError::NotInvertible(expr) => write!(f, "cell 0 = {} cannot be solved for noun and verb", expr),
+            SolveError::NoSolution(target) => write!(f, "no noun and verb in 0..=99 give {}", target),
+            SolveError::Overflow => write!(f, "solving for noun and verb overflows"),
+            SolveError::TooLarge(index) => {
+                write!(f, "the instruction at {} makes an expression of more than {} nodes", index, MAX_NODES)
+            }
+        }
+    }
+}
+
+/// A memory cell: an expression, or the index of the instruction that
+/// wrote it with a value read through a symbolic address. That is only a
+/// problem if the value is used; day2 programs start by adding the cells
+/// noun and verb point at and then overwrite the sum.
+type Cell = Result<Rc<Expr>, usize>;
+
+/// The address held in cell `at`, `None` if it depends on noun or verb.
+fn address(memory: &[Cell], index: usize, at: usize) -> Result<Option<usize>, SolveError> {
+    match memory.get(at) {
+        Some(Ok(expr)) => match **expr {
+            Expr::Const(value) if value >= 0 && (value as usize) < memory.len() => Ok(Some(value as usize)),
+            Expr::Const(_) => Err(SolveError::Unsupported(index)),
+            _ => Ok(None),
+        },
+        None => Err(SolveError::Unsupported(index)),
+        Some(Err(_)) => Ok(None),
+    }
+}
+
+/// Runs `program` with noun and verb as unknowns and returns what ends up
+/// in cell 0 when it halts.
+pub fn cell_zero(program: &[i64]) -> Result<Expr, SolveError> {
+    let mut memory: Vec<Cell> = program.iter().map(|value| Ok(Rc::new(Expr::Const(*value)))).collect();
+    if memory.len() < 3 {
+        return Err(SolveError::Unsupported(0));
+    }
+    memory[1] = Ok(Rc::new(Expr::Noun));
+    memory[2] = Ok(Rc::new(Expr::Verb));
+    let mut index = 0;
+    loop {
+        let code = match memory.get(index) {
+            Some(Ok(expr)) => match **expr {
+                Expr::Const(code) => code,
+                _ => return Err(SolveError::SymbolicAddress(index)),
+            },
+            Some(Err(_)) => return Err(SolveError::SymbolicAddress(index)),
+            None => return Err(SolveError::Unsupported(index)),
+        };
+        match code {
+            1 | 2 => {
+                let read = |offset| -> Result<Cell, SolveError> {
+                    Ok(match address(&memory, index, index + offset)? {
+                        Some(address) => memory[address].clone(),
+                        None => Err(index),
+                    })
+                };
+                let (a, b) = (read(1)?, read(2)?);
+                let target = address(&memory, index, index + 3)?.ok_or(SolveError::SymbolicAddress(index))?;
+                memory[target] = match (a, b) {
+                    (Ok(a), Ok(b)) if code == 1 => Ok(Expr::add(a, b)?),
+                    (Ok(a), Ok(b)) => Ok(Expr::mul(a, b)?),
+                    (Err(origin), _) | (_, Err(origin)) => Err(origin),
+                };
+                if memory[target].as_ref().is_ok_and(|expr| expr.nodes() > MAX_NODES) {
+                    return Err(SolveError::TooLarge(index));
+                }
+                index += 4;
+            }
+            99 => return memory.swap_remove(0).map(|expr| (*expr).clone()).map_err(SolveError::SymbolicAddress),
+            _ => return Err(SolveError::Unsupported(index)),
+        }
+    }
+}
+
+/// The noun and verb in `0..=99` that make `program` leave `target` in
+/// cell 0, taking the smallest noun, then the smallest verb, if several do.
+pub fn solve(program: &[i64], target: i64) -> Result<(i64, i64), SolveError> {
+    let expr = cell_zero(program)?;
+    let linear = match expr.linear()? {
+        Some(linear) if !linear.is_constant() => linear,
+        _ => return Err(SolveError::NotInvertible(expr)),
+    };
+    for noun in 0..=99 {
+        let rest = linear
+            .noun
+            .checked_mul(noun)
+            .and_then(|term| target.checked_sub(linear.constant)?.checked_sub(term))
+            .ok_or(SolveError::Overflow)?;
+        // `checked_rem` is `None` only for `i64::MIN % -1`, whose quotient
+        // is far outside `0..=99` anyway.
+        let verb = match linear.verb {
+            0 if rest == 0 => 0,
+            0 => continue,
+            _ if rest.checked_rem(linear.verb) == Some(0) => rest / linear.verb,
+            _ => continue,
+        };
+        if (0..=99).contains(&verb) {
+            return Ok((noun, verb));
+        }
+    }
+    Err(SolveError::NoSolution(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Leaves noun * 100 + verb in cell 0, after the usual first instruction
+    // that adds the cells noun and verb point at.
+    const PROGRAM: [i64; 14] = [1, 0, 0, 3, 2, 1, 13, 0, 1, 0, 2, 0, 99, 100];
+
+    #[test]
+    fn test_cell_zero() {
+        let expr = cell_zero(&PROGRAM).unwrap();
+        assert_eq!(expr.to_string(), "noun * 100 + verb");
+        assert_eq!(expr.linear(), Ok(Some(Linear { noun: 100, verb: 1, constant: 0 })));
+        let expr = cell_zero(&[1, 0, 0, 3, 1, 13, 2, 14, 2, 14, 1, 0, 99, 1, 0]).unwrap();
+        assert_eq!(expr.to_string(), "(1 + verb) * noun");
+        assert_eq!(expr.linear(), Ok(None));
+    }
+
+    #[test]
+    fn test_solve() {
+        assert_eq!(solve(&PROGRAM, 1234), Ok((12, 34)));
+        assert_eq!(solve(&PROGRAM, 9999), Ok((99, 99)));
+        assert_eq!(solve(&PROGRAM, 10000), Err(SolveError::NoSolution(10000)));
+        // 3 * noun + 2 * verb + 1 = 20 first holds for noun 1, verb 8.
+        let program = [
+            1, 0, 0, 3, 1, 1, 1, 26, 1, 26, 1, 26, 1, 2, 2, 27, 1, 26, 27, 0, 1, 0, 25, 0, 99, 1, 0, 0,
+        ];
+        assert_eq!(cell_zero(&program).unwrap().linear(), Ok(Some(Linear { noun: 3, verb: 2, constant: 1 })));
+        assert_eq!(solve(&program, 20), Ok((1, 8)));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = solve(&[1, 0, 0, 3, 2, 1, 2, 0, 99], 42).unwrap_err();
+        assert_eq!(error.to_string(), "cell 0 = noun * verb cannot be solved for noun and verb");
+        assert_eq!(solve(&[1, 0, 0, 3, 1, 9, 9, 0, 99, 1], 2), Err(SolveError::NotInvertible(Expr::Const(2))));
+        // What noun and verb point at ends up in cell 0.
+        assert_eq!(solve(&[1, 0, 0, 0, 99], 0), Err(SolveError::SymbolicAddress(0)));
+        // noun + verb becomes an operand of the next instruction, or the next opcode.
+        assert_eq!(solve(&[1, 0, 0, 3, 1, 1, 2, 9, 1, 9, 0, 0, 99], 0), Err(SolveError::SymbolicAddress(8)));
+        assert_eq!(solve(&[1, 0, 0, 3, 1, 1, 2, 8, 0, 0, 0, 0, 99], 0), Err(SolveError::SymbolicAddress(8)));
+        assert_eq!(solve(&[1, 0, 0, 3, 1, 0, 0, 50, 99], 0), Err(SolveError::Unsupported(4)));
+        assert_eq!(solve(&[1, 0, 0, 3, 1101, 0, 0, 0, 99], 0), Err(SolveError::Unsupported(4)));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // noun * 2^62 * 2^62: the coefficient of noun overflows.
+        let expr = cell_zero(&[1, 0, 0, 3, 2, 1, 13, 0, 2, 0, 13, 0, 99, 1 << 62]).unwrap();
+        assert_eq!(expr.linear(), Err(SolveError::Overflow));
+        // noun * 2^62 + noun * 2^62: adding the coefficients overflows.
+        let expr = cell_zero(&[1, 0, 0, 3, 2, 1, 13, 0, 1, 0, 0, 0, 99, 1 << 62]).unwrap();
+        assert_eq!(expr.linear(), Err(SolveError::Overflow));
+        // noun * 2^62 is linear, but 2 * 2^62 is not an i64.
+        let program = [1, 0, 0, 3, 2, 1, 9, 0, 99, 1 << 62];
+        assert_eq!(solve(&program, 1 << 62), Ok((1, 0)));
+        assert_eq!(solve(&program, 5), Err(SolveError::Overflow));
+        // 2^62 + 2^62 and 2^62 * 2^62 overflow when the constants are folded.
+        assert_eq!(cell_zero(&[1, 0, 0, 3, 1, 9, 9, 0, 99, 1 << 62]), Err(SolveError::Overflow));
+        assert_eq!(cell_zero(&[1, 0, 0, 3, 2, 9, 9, 0, 99, 1 << 62]), Err(SolveError::Overflow));
+    }
+
+    // Sets a cell to noun + verb, doubles it `times` times by adding it to
+    // itself, and adds it to itself once more into cell 0.
+    fn doubling(times: usize) -> Vec<i64> {
+        let cell = 13 + 4 * times as i64;
+        let mut program = vec![1, 0, 0, 3, 1, 1, 2, cell];
+        for _ in 0..times {
+            program.extend(&[1, cell, cell, cell]);
+        }
+        program.extend(&[1, cell, cell, 0, 99, 0]);
+        program
+    }
+
+    #[test]
+    fn test_too_large() {
+        let expr = cell_zero(&doubling(9)).unwrap();
+        assert_eq!(expr.nodes(), 4095);
+        assert_eq!(expr.linear(), Ok(Some(Linear { noun: 1024, verb: 1024, constant: 0 })));
+        // The twelfth doubling, at 8 + 4 * 11, makes 16383 nodes. Copying
+        // whole trees, the rest would never finish.
+        assert_eq!(cell_zero(&doubling(60)), Err(SolveError::TooLarge(52)));
+        assert_eq!(
+            solve(&doubling(60), 0).unwrap_err().to_string(),
+            "the instruction at 52 makes an expression of more than 10000 nodes"
+        );
+    }
+}
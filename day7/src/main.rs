@@ -1,217 +1,126 @@
 mod permutations;
-use std::io::BufReader;
-use std::io::BufRead;
-use std::io;
-use std::fs;
-use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use intcode::{read_program, Closure, Error, Machine};
 use permutations::*;
 
-#[derive(Debug)]
-struct Instruction {
-    code: i64,
-    modes: HashMap<i64, bool>
-}
-
-fn parse_instruction(instruction: i64) -> Instruction {
-    let opcode = instruction % 100;
-    let mode1 = instruction % 1000 / 100;
-    let mode2 = instruction % 10000 / 1000;
-    let mode3 = instruction / 10000;
-    let mut modes = HashMap::new();
-    modes.insert(1, mode1 == 1);
-    modes.insert(2, mode2 == 1);
-    modes.insert(3, mode3 == 1);
-    return Instruction { code: opcode, modes: modes };
-}
-
-
-fn file_to_vec(filename: String) -> io::Result<Vec<i64>> {
-    let file_in = fs::File::open(filename)?;
-    let file_reader = BufReader::new(file_in);
-    let rows : Vec<String> = file_reader.lines().filter_map(io::Result::ok).collect();
-    let v: Vec<i64> = rows[0].split(",").map(|x| x.parse::<i64>().expect("parse error")).collect();
-
-    return Ok(v);
-}
-
-fn get_value(idx: usize, mode: bool, program: &Vec<i64>) -> i64{
-    let pnt = program[idx];
-    if mode {
-        return pnt;
+/// Runs until the first output and returns it, or `None` if the program
+/// halts without output.
+fn run_program(program: &mut Vec<i64>, inputs: Vec<i64>) -> Result<Option<i64>, Error>{
+    let mut machine = Machine::new(program);
+    for input in inputs {
+        machine.push_input(input);
     }
-    else {
-        return program[pnt as usize];
-    }
-}
-
-fn get_index (idx: usize, mode: bool, program: &Vec<i64>) -> usize {
-    let pnt = program[idx];
-    if mode {
-        return idx;
-    }
-    else{
-        return pnt as usize;
-    }
-}
-
-
-fn run_program(program: &mut Vec<i64>, inputs: Vec<i64>) -> i64{
-    let mut index = 0;
-    let mut input_index = 0;
-    loop {
-        let instruction = parse_instruction(program[index]);
-        let mode1 = instruction.modes[&1];
-        let mode2 = instruction.modes[&2];
-        let mode3 = instruction.modes[&3];
-        match instruction.code {
-            1 | 2 => {
-                let val1 = get_value(index + 1, mode1, program);
-                let val2 = get_value(index + 2, mode2, program);
-                let insert_index = get_index(index + 3, mode3, program);
-        
-                program[insert_index] = 
-                    if instruction.code == 1 {
-                        val1 + val2
-                    }
-                    else {
-                        val1 * val2
-                    };
-                index += 4;
-            },
-            3 => {
-                let idx = program[index + 1];
-                program[idx as usize] = inputs[input_index];
-                if input_index < inputs.len() - 1 {
-                    input_index +=1;
-                }
-                //println!("input: {:?}", input_index);
-                index += 2;
-            },
-            4 => {
-                let idx = program[index + 1];
-                return program[idx as usize];
-            },
-            5 => {
-                let val1 = get_value(index + 1, mode1, program);
-                if val1 != 0 {
-                    index = get_value(index + 2, mode2, program) as usize;
-                }
-                else {
-                    index += 3;
-                }
-            },
-            6 => {
-                let val1 = get_value(index + 1, mode1, program);
-                if val1 == 0 {
-                    index = get_value(index + 2, mode2, program) as usize;
-                }
-                else {
-                    index += 3;
-                }
-            },
-            7 => {
-                let val1 = get_value(index + 1, mode1, program);
-                let val2 = get_value(index + 2, mode2, program);
-                let val3 = get_index(index + 3, mode3, program);
-                if val1 < val2 {
-                    program[val3] = 1;
-                }
-                else {
-                    program[val3] = 0;
-                }
-                index += 4;
-            },
-            8 => {
-                let val1 = get_value(index + 1, mode1, program);
-                let val2 = get_value(index + 2, mode2, program);
-                let val3 = get_index(index + 3, mode3, program);
-                if val1 == val2 {
-                    program[val3] = 1;
-                }
-                else {
-                    program[val3] = 0;
-                }
-                index += 4;
-            },
-            99 => {
-                panic!("Halt");
-            },
-            _ => {
-                panic!("Wrong opcode: {:?}", instruction.code);
-            }
+    while !machine.is_halted() {
+        machine.step()?;
+        if let Some(value) = machine.take_output().pop() {
+            *program = machine.memory();
+            return Ok(Some(value));
         }
     }
-} 
+    Ok(None)
+}
 
-fn start_program(program: &Vec<i64>, input: Vec<i64>)-> i64 {
-    let mut program = program.clone();
-    return run_program(&mut program, input);
+fn start_program(program: &[i64], input: Vec<i64>)-> Result<Option<i64>, Error> {
+    let mut program = program.to_vec();
+    run_program(&mut program, input)
 }
 
-fn run_amplifier(program: &Vec<i64>, phase: i64, input: i64) -> i64{
+fn run_amplifier(program: &[i64], phase: i64, input: i64) -> Result<Option<i64>, Error>{
     let inputs = vec![phase, input];
-    return start_program(program, inputs);
+    start_program(program, inputs)
 }
 
-fn run_amplifiers(program: &Vec<i64>, phases: &Vec<i64>) -> i64{
-    return phases.into_iter().fold(0, |output, phase| run_amplifier(program, *phase, output));
+/// The signal out of the last amplifier, or `None` if one of them halts
+/// without passing a signal on.
+fn run_amplifiers(program: &[i64], phases: &[i64]) -> Result<Option<i64>, Error>{
+    phases.iter().try_fold(Some(0), |signal, phase| match signal {
+        Some(signal) => run_amplifier(program, *phase, signal),
+        None => Ok(None),
+    })
 }
 
-fn get_max_thruster(program: &Vec<i64>)->i64{
-    return permutations(0, 4).map(|v| run_amplifiers(program, &v.iter().map(|x| *x as i64).collect::<Vec<i64>>())).max().unwrap();
+fn get_max_thruster(program: &[i64])->Result<Option<i64>, Error>{
+    let thrusts = permutations(0, 4).map(|v| run_amplifiers(program, &v.iter().map(|x| *x as i64).collect::<Vec<i64>>())).collect::<Result<Vec<Option<i64>>, Error>>()?;
+    Ok(thrusts.into_iter().flatten().max())
 }
 
-// does not compute.... :-(
-fn run_amplifiers_loop(program: &Vec<i64>, phases: &Vec<i64>)-> i64{
-    let program1 = program.clone();
-    let program2 = program.clone();
-    let program3 = program.clone();
-    let program4 = program.clone();
-    let program5 = program.clone();
-    let mut programs = vec![program1, program2, program3, program4, program5]; 
-
-    let mut input = 0;
-
-    for i in 0 .. 5 {
-        input = run_program(&mut programs[i], vec![phases[i], input]);
-        println!("input: {:?}", input);
-    }
-
-    let mut index = 0;
-    let mut counter = 0;
-    while counter < 10000 {
-        if index == 0 {
-            println!("program1: {:?}", programs[0]);
-        }
-        input = run_program(&mut programs[index], vec![input]);
-        println!("input: {:?}", input);
-        if index == programs.len() - 1{
-            index = 0;
-        } else {
-            index += 1;
-        }
-        counter += 1;
-        if counter % 1000 == 0 {
-            println!("counter: {:?}", counter)
-        }
-    }
-    return input;
+fn run_amplifiers_loop(program: &[i64], phases: &[i64])-> Result<i64, Error>{
+    // Amplifier i reads from channel i and writes to channel i + 1, the last one back to the first.
+    let (senders, receivers): (Vec<Sender<i64>>, Vec<Receiver<i64>>) = phases.iter().map(|phase| {
+        let (sender, receiver) = channel();
+        sender.send(*phase).unwrap();
+        (sender, receiver)
+    }).unzip();
+    senders[0].send(0).unwrap();
+    let handles: Vec<_> = receivers.into_iter().enumerate().map(|(i, input)| {
+        let output = senders[(i + 1) % senders.len()].clone();
+        let mut machine = Machine::new(program);
+        thread::spawn(move || {
+            let mut signal = 0;
+            machine.run_with(input, Closure(|value| {
+                signal = value;
+                let _ = output.send(value);
+            }))?;
+            Ok(signal)
+        })
+    }).collect();
+    drop(senders);
+    let signals = handles.into_iter().map(|handle| handle.join().expect("amplifier panicked")).collect::<Result<Vec<i64>, Error>>()?;
+    Ok(*signals.last().unwrap())
+}
+fn get_max_thruster_loop(program: &[i64])->Result<i64, Error>{
+    let thrusts = permutations(5, 9).map(|v| run_amplifiers_loop(program, &v.iter().map(|x| *x as i64).collect::<Vec<i64>>())).collect::<Result<Vec<i64>, Error>>()?;
+    Ok(thrusts.into_iter().max().unwrap())
 }
 
-fn get_max_thruster_loop(program: &Vec<i64>)->i64{
-    return permutations(5, 9).map(|v| run_amplifiers(program, &v.iter().map(|x| *x as i64).collect::<Vec<i64>>())).max().unwrap();
+// Task 1: 273814
+// Task 2: 34579864
+fn main() {
+    let row = read_program("data.txt");
+    match row {
+    Ok(numbers) => {
+        match (get_max_thruster(&numbers), get_max_thruster_loop(&numbers)) {
+            (Ok(Some(answer1)), Ok(answer2)) => {
+                println!("Task 1: {:?}", answer1);
+                println!("Task 2: {:?}", answer2);
+            },
+            (Ok(None), _) => println!("No order of phases gets a signal through the amplifiers"),
+            (Err(e), _) | (_, Err(e)) => println!("Error running program: {}", e)
+        }
+    },
+    Err(e) => println!("Error reading file: {}", e)
+    }   
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use intcode::conformance::corpus;
 
     #[test]
-    fn test_jump_position_mode_zero_input(){
-        let program = vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9];
-        let input = vec![0];
-        let result = start_program(&program, input);
-        assert_eq!(result, 0);
+    fn test_conformance() {
+        for case in corpus() {
+            let result = start_program(&case.program, case.input.clone());
+            assert_eq!(result, Ok(case.output.first().copied()), "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn test_halt_without_output(){
+        assert_eq!(start_program(&[3,5,3,5,99,0], vec![0, 1]), Ok(None));
+        assert_eq!(run_amplifiers(&[3,5,3,5,99,0], &[0, 1, 2]), Ok(None));
+        assert_eq!(get_max_thruster(&[3,5,3,5,99,0]), Ok(None));
+    }
+
+    #[test]
+    fn test_permutations_not_starting_at_zero(){
+        let mut result: Vec<Vec<usize>> = permutations(5, 9).collect();
+        assert_eq!(result.len(), 120);
+        result.sort();
+        result.dedup();
+        assert_eq!(result.len(), 120);
+        assert!(result.iter().all(|v| v.iter().all(|x| (5..10).contains(x))));
     }
 
     #[test]
@@ -219,7 +128,7 @@ mod tests {
         let program = vec![3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0];
         let phases = vec![4,3,2,1,0];
         let result = run_amplifiers(&program, &phases);
-        assert_eq!(result, 43210)
+        assert_eq!(result, Ok(Some(43210)))
     }
 
     #[test]
@@ -227,7 +136,7 @@ mod tests {
         let program = vec![3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0];
         let phases = vec![0,1,2,3,4];
         let result = run_amplifiers(&program, &phases);
-        assert_eq!(result, 54321)
+        assert_eq!(result, Ok(Some(54321)))
     }
 
     #[test]
@@ -235,14 +144,14 @@ mod tests {
         let program = vec![3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0];
         let phases = vec![1,0,4,3,2];
         let result = run_amplifiers(&program, &phases);
-        assert_eq!(result, 65210)
+        assert_eq!(result, Ok(Some(65210)))
     }
 
     #[test]
     fn get_max_thrusters(){
         let program = vec![3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0];
         let result = get_max_thruster(&program);
-        assert_eq!(result, 43210)
+        assert_eq!(result, Ok(Some(43210)))
     }
     
 
@@ -250,51 +159,37 @@ mod tests {
     fn test_get_max_thrusters_2(){
         let program = vec![3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0];
         let result = get_max_thruster(&program);
-        assert_eq!(result, 54321)
+        assert_eq!(result, Ok(Some(54321)))
     }
 
     #[test]
     fn test_get_max_thrusters_3(){
         let program = vec![3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0];
         let result = get_max_thruster(&program);
-        assert_eq!(result, 65210)
+        assert_eq!(result, Ok(Some(65210)))
     }
 
-    //#[test]
+    #[test]
     fn test_max_thrust_loop(){
         let program = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
         let phases = vec![9,8,7,6,5];
         let result = run_amplifiers_loop(&program, &phases);
-        assert_eq!(result, 139629729)
+        assert_eq!(result, Ok(139629729))
     }  
 
-    //#[test]
+    #[test]
     fn test_max_thrust_loop_2(){
         let program = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
         let phases = vec![9,8,7,6,5];
         let result = run_amplifiers_loop(&program, &phases);
-        assert_eq!(result, 139629729)
+        assert_eq!(result, Ok(139629729))
     }    
 
-    //#[test]
+    #[test]
     fn test_max_thrust_loop_3(){
         let program = vec![3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,-5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10];
         let phases = vec![9,7,8,5,6];
         let result = run_amplifiers_loop(&program, &phases);
-        assert_eq!(result, 18216)
+        assert_eq!(result, Ok(18216))
     }  
 }
-
-// Task 1: 273814
-fn main() {
-    let row = file_to_vec("data.txt".to_string());
-    match row {
-    Ok(numbers) => {
-        let answer1 = get_max_thruster(&numbers);
-        println!("Task 1: {:?}", answer1);
-        //let answer2 = get_max_thruster_loop(&numbers);
-        //sprintln!("Task 2: {:?}", answer2);
-    },
-    Err(e) => println!("Error reading file: {:?}", e)
-    }   
-}
\ No newline at end of file
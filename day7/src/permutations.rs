@@ -1,19 +1,18 @@
 pub fn permutations(min:usize, max: usize) -> Permutations {
-    Permutations { idxs: (min..max + 1).collect(), swaps: vec![min; max + 1], i: min, min: min }
+    Permutations { idxs: (min..max + 1).collect(), swaps: vec![0; max - min + 1], i: 0 }
 }
  
 pub struct Permutations {
     idxs: Vec<usize>,
     swaps: Vec<usize>,
-    i: usize,
-    min: usize
+    i: usize
 }
  
 impl Iterator for Permutations {
     type Item = Vec<usize>;
  
     fn next(&mut self) -> Option<Self::Item> {
-        if self.i > self.min {
+        if self.i > 0 {
             loop {
                 if self.i >= self.swaps.len() { return None; }
                 if self.swaps[self.i] < self.i { break; }
@@ -0,0 +1,77 @@
+//! Compares the flat-memory machine, with and without the decode cache
+//! and compiled blocks, against the `HashMap` interpreter the days used before.
+//!
+//! Run with `cargo bench`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use intcode::{read_program, Machine};
+
+#[path = "../tests/legacy/mod.rs"]
+mod legacy;
+
+fn load(day: &str) -> Vec<i64> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(day).join("data.txt");
+    read_program(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// Best of a few runs, to keep noise from other processes out.
+fn time<F: FnMut() -> Vec<i64>>(mut run: F) -> (Duration, Vec<i64>) {
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut output = Vec::new();
+    for _ in 0..5 {
+        let start = Instant::now();
+        output = run();
+        best = best.min(start.elapsed());
+    }
+    (best, output)
+}
+
+fn main() {
+    let cases = [("day5", 5), ("day9", 2), ("day13", 1)];
+    println!(
+        "{:<8} {:>12} {:>12} {:>12} {:>12} {:>9}",
+        "program", "hashmap", "flat", "flat+cache", "compiled", "speedup"
+    );
+    for (day, input) in cases.iter() {
+        let program = load(day);
+        let (legacy, expected) = time(|| {
+            let mut memory: HashMap<i64, i64> = program.iter().enumerate().map(|(i, v)| (i as i64, *v)).collect();
+            legacy::run_program(&mut memory, &[*input])
+        });
+        let (flat, output) = time(|| {
+            let mut machine = Machine::new(&program);
+            machine.push_input(*input);
+            machine.run().unwrap();
+            machine.take_output()
+        });
+        assert_eq!(output, expected);
+        let (cached, output) = time(|| {
+            let mut machine = Machine::new(&program);
+            machine.enable_cache();
+            machine.push_input(*input);
+            machine.run().unwrap();
+            machine.take_output()
+        });
+        assert_eq!(output, expected);
+        let (compiled, output) = time(|| {
+            let mut machine = Machine::new(&program);
+            machine.enable_compilation();
+            machine.push_input(*input);
+            machine.run().unwrap();
+            machine.take_output()
+        });
+        assert_eq!(output, expected);
+        println!(
+            "{:<8} {:>12?} {:>12?} {:>12?} {:>12?} {:>8.1}x",
+            day,
+            legacy,
+            flat,
+            cached,
+            compiled,
+            legacy.as_secs_f64() / flat.min(cached).min(compiled).as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Talking to programs that print text and read commands as character
+//! codes, a line at a time.
+//!
+//! Output in `0..=127` is text; anything else, like the final answer some
+//! programs print after their text, is kept apart as a number.
+
+use std::io::{BufRead, Write};
+
+use crate::error::{Error, ErrorKind};
+use crate::machine::{Machine, State};
+
+/// What the program printed before it stopped to wait for input or halted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    /// Complete lines, without their newlines.
+    pub lines: Vec<String>,
+    /// Text after the last newline, usually a prompt.
+    pub partial: String,
+    /// Output outside the ASCII range, in the order it came.
+    pub values: Vec<i64>,
+    pub halted: bool,
+}
+
+impl Transcript {
+    /// The text as printed, newlines included.
+    pub fn text(&self) -> String {
+        let mut text: String = self.lines.iter().map(|line| format!("{}\n", line)).collect();
+        text.push_str(&self.partial);
+        text
+    }
+}
+
+pub struct Ascii {
+    machine: Machine,
+}
+
+impl Ascii {
+    pub fn new(machine: Machine) -> Ascii {
+        Ascii { machine }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    /// Queues the character codes of `text` as input. Characters outside
+    /// ASCII are sent as their Unicode code points, which are above 127.
+    pub fn send(&mut self, text: &str) {
+        for c in text.chars() {
+            self.machine.push_input(c as i64);
+        }
+    }
+
+    /// Queues `line` followed by a newline.
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.machine.push_input(10);
+    }
+
+    /// Runs until the program needs input it has not been sent, or halts.
+    pub fn run(&mut self) -> Result<Transcript, Error> {
+        let mut transcript = Transcript::default();
+        loop {
+            match self.machine.resume()? {
+                State::Output(10) => transcript.lines.push(std::mem::take(&mut transcript.partial)),
+                State::Output(value @ 0..=127) => transcript.partial.push(value as u8 as char),
+                State::Output(value) => transcript.values.push(value),
+                State::NeedsInput => return Ok(transcript),
+                State::Halted => {
+                    transcript.halted = true;
+                    return Ok(transcript);
+                }
+            }
+        }
+    }
+
+    /// Lets a person play the program: prints what it prints, numbers on
+    /// lines of their own, and sends it each line read from `input`. Returns
+    /// [`State::Halted`] when the program halts, or [`State::NeedsInput`]
+    /// when `input` ends first. Fails with [`ErrorKind::OutputClosed`] at
+    /// the instruction the program stopped at if writing to `output` fails.
+    pub fn interact<R: BufRead, T: Write>(&mut self, mut input: R, mut output: T) -> Result<State, Error> {
+        loop {
+            let transcript = self.run()?;
+            let mut text = transcript.text();
+            for value in &transcript.values {
+                text.push_str(&format!("{}\n", value));
+            }
+            if output.write_all(text.as_bytes()).and_then(|_| output.flush()).is_err() {
+                let index = self.machine.index();
+                let instruction = self.machine.read(index);
+                return Err(Error { kind: ErrorKind::OutputClosed, index, instruction });
+            }
+            if transcript.halted {
+                return Ok(State::Halted);
+            }
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) | Err(_) => return Ok(State::NeedsInput),
+                Ok(_) => self.send_line(line.trim_end_matches(['\n', '\r'])),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use std::io;
+
+    // Asks for a name, prints it in upper case and then the number 1000.
+    fn greeter() -> Ascii {
+        let program = assemble(
+            "        arb #prompt
+             print:  jz [rb+0], #read
+                     out [rb+0]
+                     arb #1
+                     jnz #1, #print
+             read:   in [c]
+                     eq [c], #10, [t]
+                     jnz [t], #done
+                     add [c], #-32, [c]
+                     out [c]
+                     jnz #1, #read
+             done:   out #10
+                     out #1000
+                     hlt
+             c:      data 0
+             t:      data 0
+             prompt: data 110, 97, 109, 101, 63, 10, 62, 0",
+        )
+        .unwrap();
+        Ascii::new(Machine::new(&program))
+    }
+
+    #[test]
+    fn test_run() {
+        let mut ascii = greeter();
+        let transcript = ascii.run().unwrap();
+        assert_eq!((transcript.lines, transcript.partial.as_str()), (vec!["name?".to_string()], ">"));
+        assert!(!transcript.halted);
+        ascii.send_line("bob");
+        let transcript = ascii.run().unwrap();
+        assert_eq!(transcript.text(), "BOB\n");
+        assert_eq!(transcript.values, vec![1000]);
+        assert!(transcript.halted);
+        assert!(ascii.into_machine().is_halted());
+    }
+
+    #[test]
+    fn test_interact() {
+        let mut output = Vec::new();
+        assert_eq!(greeter().interact("ann\r\n".as_bytes(), &mut output), Ok(State::Halted));
+        assert_eq!(String::from_utf8(output).unwrap(), "name?\n>ANN\n1000\n");
+        let mut output = Vec::new();
+        assert_eq!(greeter().interact(&b""[..], &mut output), Ok(State::NeedsInput));
+        assert_eq!(output, b"name?\n>");
+    }
+
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_interact_closed_output() {
+        let result = greeter().interact("ann\n".as_bytes(), ClosedPipe);
+        assert_eq!(result, Err(Error { kind: ErrorKind::OutputClosed, index: 12, instruction: 3 }));
+    }
+
+    #[test]
+    fn test_send_non_ascii() {
+        // 'é' goes in as 233, and the 201 the greeter makes of it comes
+        // back as a number rather than text.
+        let mut ascii = greeter();
+        ascii.run().unwrap();
+        ascii.send_line("é");
+        let transcript = ascii.run().unwrap();
+        assert_eq!(transcript.text(), "\n");
+        assert_eq!(transcript.values, vec![201, 1000]);
+    }
+}
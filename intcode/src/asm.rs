@@ -0,0 +1,327 @@
+//! A small assembler for writing Intcode programs by hand.
+//!
+//! ```text
+//! ; echo numbers until a zero is read
+//! loop:   in [value]
+//!         jz [value], #done
+//!         out [value]
+//!         jnz #1, #loop
+//! done:   hlt
+//! value:  data 0
+//! ```
+//!
+//! Operands are `#x` (immediate), `[x]` (position) or `[rb+x]` / `[rb-x]`
+//! (relative), where `x` is a number, a label or `label+n` / `label-n`.
+//! `data` emits its operands as plain words. Comments start with `;`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::machine::Mode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Mnemonic, opcode and number of operands for every Intcode instruction.
+pub(crate) const MNEMONICS: [(&str, i64, usize); 10] = [
+    ("add", 1, 3),
+    ("mul", 2, 3),
+    ("in", 3, 1),
+    ("out", 4, 1),
+    ("jnz", 5, 2),
+    ("jz", 6, 2),
+    ("lt", 7, 3),
+    ("eq", 8, 3),
+    ("arb", 9, 1),
+    ("hlt", 99, 0),
+];
+
+/// The operand (counted from 1) that an opcode writes to, if any.
+pub(crate) fn write_operand(code: i64) -> Option<usize> {
+    match code {
+        1 | 2 | 7 | 8 => Some(3),
+        3 => Some(1),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+enum Value {
+    Number(i64),
+    Label(String, i64),
+}
+
+#[derive(Debug)]
+struct Operand {
+    mode: Mode,
+    value: Value,
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction(i64, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    if let Ok(number) = text.parse::<i64>() {
+        return Ok(Value::Number(number));
+    }
+    let (label, offset) = match text.find(['+', '-']) {
+        Some(pos) => {
+            let offset = text[pos..].replace(' ', "");
+            let offset = offset
+                .trim_start_matches('+')
+                .parse::<i64>()
+                .map_err(|_| format!("invalid offset in {:?}", text))?;
+            (text[..pos].trim(), offset)
+        }
+        None => (text, 0),
+    };
+    if !is_identifier(label) || label == "rb" {
+        return Err(format!("invalid value {:?}", text));
+    }
+    Ok(Value::Label(label.to_string(), offset))
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix('#') {
+        return Ok(Operand { mode: Mode::Immediate, value: parse_value(rest)? });
+    }
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let inner = inner.trim();
+        if let Some(rest) = inner.strip_prefix("rb") {
+            let rest = rest.trim();
+            if rest.is_empty() {
+                return Ok(Operand { mode: Mode::Relative, value: Value::Number(0) });
+            }
+            let value = if let Some(rest) = rest.strip_prefix('+') {
+                parse_value(rest)?
+            } else if let Some(rest) = rest.strip_prefix('-') {
+                match parse_value(rest)? {
+                    Value::Number(number) => match number.checked_neg() {
+                        Some(number) => Value::Number(number),
+                        None => return Err(format!("offset out of range in {:?}", text)),
+                    },
+                    Value::Label(..) => return Err(format!("cannot negate a label in {:?}", text)),
+                }
+            } else {
+                return Err(format!("invalid relative operand {:?}", text));
+            };
+            return Ok(Operand { mode: Mode::Relative, value });
+        }
+        return Ok(Operand { mode: Mode::Position, value: parse_value(inner)? });
+    }
+    Err(format!("operand {:?} needs a mode: #x, [x] or [rb+x]", text))
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        text.split(',').map(|s| s.trim()).collect()
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    let (name, rest) = match text.find(char::is_whitespace) {
+        Some(pos) => (&text[..pos], &text[pos..]),
+        None => (text, ""),
+    };
+    let name = name.to_lowercase();
+    if name == "data" {
+        let values = split_operands(rest)
+            .into_iter()
+            .map(parse_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.is_empty() {
+            return Err("data needs at least one value".to_string());
+        }
+        return Ok(Statement::Data(values));
+    }
+    let (code, count) = match MNEMONICS.iter().find(|(m, _, _)| *m == name) {
+        Some((_, code, count)) => (*code, *count),
+        None => return Err(format!("unknown mnemonic {:?}", name)),
+    };
+    let operands = split_operands(rest)
+        .into_iter()
+        .map(parse_operand)
+        .collect::<Result<Vec<_>, _>>()?;
+    if operands.len() != count {
+        return Err(format!("{} takes {} operand(s), got {}", name, count, operands.len()));
+    }
+    if let Some(position) = write_operand(code) {
+        if operands[position - 1].mode == Mode::Immediate {
+            return Err(format!("{} cannot write to an immediate operand", name));
+        }
+    }
+    Ok(Statement::Instruction(code, operands))
+}
+
+fn mode_digit(mode: Mode) -> i64 {
+    match mode {
+        Mode::Position => 0,
+        Mode::Immediate => 1,
+        Mode::Relative => 2,
+    }
+}
+
+/// Assembles `source` into the program words that
+/// [`read_program`](crate::read_program) would read from the program's file.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let error = |message: String| AsmError { line: number, message };
+        let mut text = line.split(';').next().unwrap().trim();
+        while let Some(pos) = text.find(':') {
+            let label = text[..pos].trim();
+            if !is_identifier(label) || label == "rb" {
+                return Err(error(format!("invalid label {:?}", label)));
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(format!("duplicate label {:?}", label)));
+            }
+            text = text[pos + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(text).map_err(error)?;
+        address += match &statement {
+            Statement::Instruction(_, operands) => 1 + operands.len() as i64,
+            Statement::Data(values) => values.len() as i64,
+        };
+        statements.push((number, statement));
+    }
+
+    let mut program = Vec::new();
+    for (number, statement) in statements {
+        let resolve = |value: &Value| match value {
+            Value::Number(number) => Ok(*number),
+            Value::Label(label, offset) => match labels.get(label) {
+                Some(address) => address.checked_add(*offset).ok_or_else(|| AsmError {
+                    line: number,
+                    message: format!("label {:?} plus {} is out of range", label, offset),
+                }),
+                None => Err(AsmError { line: number, message: format!("unknown label {:?}", label) }),
+            },
+        };
+        match statement {
+            Statement::Instruction(code, operands) => {
+                let modes = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(i, operand)| mode_digit(operand.mode) * 10_i64.pow(i as u32 + 2))
+                    .sum::<i64>();
+                program.push(code + modes);
+                for operand in operands {
+                    program.push(resolve(&operand.value)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    program.push(resolve(&value)?);
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::run_program;
+
+    #[test]
+    fn test_assemble_compare_with_8() {
+        // Same program as the day5 example 3,9,8,9,10,9,4,9,99,-1,8
+        let program = assemble(
+            "in [x]
+             eq [x], [eight], [x]
+             out [x]
+             hlt
+             x: data -1
+             eight: data 8",
+        )
+        .unwrap();
+        assert_eq!(program, vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+    }
+
+    #[test]
+    fn test_assemble_modes() {
+        let program = assemble("add [rb+3], #-1, [rb-2]\nmul [5], #7, [rb]\n arb #10").unwrap();
+        assert_eq!(program, vec![21201, 3, -1, -2, 21002, 5, 7, 0, 109, 10]);
+    }
+
+    #[test]
+    fn test_assemble_labels_and_data() {
+        let source = "
+            ; count down from the input and echo each value
+            start:  in [n]
+            loop:   out [n]
+                    add [n], #-1, [n]
+                    jnz [n], #loop
+                    hlt
+            n:      data 0
+            table:  data n, n+1, table-1
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(&program[program.len() - 4..], &[0, 12, 13, 12]);
+        assert_eq!(run_program(&program, &[3]), Ok(vec![3, 2, 1]));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = assemble("in [a]\n\nfoo [a]\na: data 0").unwrap_err();
+        assert_eq!(error, AsmError { line: 3, message: "unknown mnemonic \"foo\"".to_string() });
+        assert_eq!(assemble("hlt\nout [missing]").unwrap_err().line, 2);
+        assert_eq!(assemble("add [1], #2").unwrap_err().line, 1);
+        assert_eq!(assemble("add [1], [2], #3").unwrap_err().line, 1);
+        assert_eq!(assemble("x: hlt\nx: hlt").unwrap_err().line, 2);
+        assert_eq!(assemble("out 5").unwrap_err().line, 1);
+        assert_eq!(assemble("  data").unwrap_err().to_string(), "line 1: data needs at least one value");
+    }
+
+    #[test]
+    fn test_out_of_range_offsets() {
+        assert_eq!(assemble("out [rb--9223372036854775807]"), Ok(vec![204, 9223372036854775807]));
+        assert_eq!(
+            assemble("hlt\nout [rb--9223372036854775808]").unwrap_err().to_string(),
+            "line 2: offset out of range in \"[rb--9223372036854775808]\""
+        );
+        assert_eq!(assemble("x: data x+9223372036854775807"), Ok(vec![9223372036854775807]));
+        assert_eq!(
+            assemble("hlt\nx: data x+9223372036854775807").unwrap_err().to_string(),
+            "line 2: label \"x\" plus 9223372036854775807 is out of range"
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use std::env;
+use std::process;
+
+use intcode::{control_flow, read_program};
+
+/// Prints the control-flow graph of a program in Graphviz DOT format:
+/// `intcode-cfg data.txt | dot -Tsvg > cfg.svg`
+fn main() {
+    let filename = match env::args().nth(1) {
+        Some(filename) => filename,
+        None => {
+            eprintln!("usage: intcode-cfg <program file>");
+            process::exit(2);
+        }
+    };
+    match read_program(&filename) {
+        Ok(program) => print!("{}", control_flow(&program).to_dot()),
+        Err(e) => eprintln!("Error reading {}: {}", filename, e),
+    }
+}
@@ -0,0 +1,25 @@
+use std::env;
+use std::io;
+use std::process;
+
+use intcode::{read_program, Debugger, Machine};
+
+fn main() {
+    let filename = match env::args().nth(1) {
+        Some(filename) => filename,
+        None => {
+            eprintln!("usage: intcode-debug <program file>");
+            process::exit(2);
+        }
+    };
+    match read_program(&filename) {
+        Ok(program) => {
+            let mut debugger = Debugger::new(Machine::new(&program));
+            let stdin = io::stdin();
+            if let Err(e) = debugger.repl(stdin.lock(), io::stdout()) {
+                eprintln!("Error: {:?}", e);
+            }
+        }
+        Err(e) => eprintln!("Error reading {}: {}", filename, e),
+    }
+}
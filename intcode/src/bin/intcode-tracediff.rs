@@ -0,0 +1,48 @@
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+
+use intcode::first_divergence;
+
+fn open(filename: &str) -> BufReader<File> {
+    match File::open(filename) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            eprintln!("Error reading {}: {}", filename, e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Finds the first instruction where two traces written with
+/// `intcode --trace-json` differ. Exits with 1 if they differ, like `diff`.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 2 {
+        eprintln!("usage: intcode-tracediff <trace file> <trace file>");
+        process::exit(2);
+    }
+    let divergence = match first_divergence(open(&args[0]), open(&args[1])) {
+        Ok(divergence) => divergence,
+        Err(e) => {
+            eprintln!("Error reading traces: {}", e);
+            process::exit(2);
+        }
+    };
+    let divergence = match divergence {
+        Some(divergence) => divergence,
+        None => {
+            println!("the traces are identical");
+            return;
+        }
+    };
+    println!("first difference at line {}:", divergence.line);
+    for (filename, line) in args.iter().zip(&[divergence.left, divergence.right]) {
+        match line {
+            Some(line) => println!("{}: {}", filename, line),
+            None => println!("{}: (trace ended)", filename),
+        }
+    }
+    process::exit(1);
+}
@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufWriter, Write};
+use std::process;
+
+use intcode::{decode, read_program, Ascii, Input, LineInput, Machine, State};
+
+const USAGE: &str = "usage: intcode [options] <program file> [input...]
+
+Runs an Intcode program, giving it the inputs listed after the file name.
+
+options:
+  -i, --input-file <file>  also read comma or whitespace separated inputs from a file
+      --stdin              when the inputs run out, read one number per line from stdin
+  -a, --ascii              print output as ASCII text, with values above 127 as numbers
+      --interactive        play a text program: print its text and send it each
+                           line typed as ASCII, after any inputs given
+  -p, --patch <addr>=<v>   write v to addr before running, e.g. -p 1=12 -p 2=2 for day2
+  -r, --read <addr>        print the value at addr after the program halts
+  -t, --trace              print every instruction to stderr before it runs
+      --trace-json <file>  write a JSON record per executed instruction to a file,
+                           compare two such files with intcode-tracediff
+  -s, --max-steps <n>      stop with an error after n instructions
+      --profile            print a profile of the run to stderr
+  -h, --help               show this message";
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    program: String,
+    inputs: Vec<i64>,
+    input_files: Vec<String>,
+    stdin: bool,
+    ascii: bool,
+    interactive: bool,
+    patches: Vec<(i64, i64)>,
+    reads: Vec<i64>,
+    trace: bool,
+    trace_json: Option<String>,
+    max_steps: Option<u64>,
+    profile: bool,
+}
+
+fn number<T: std::str::FromStr>(text: &str, what: &str) -> Result<T, String> {
+    text.trim().parse().map_err(|_| format!("invalid {} {:?}", what, text))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut program = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "-i" | "--input-file" => options.input_files.push(value(&arg)?),
+            "--stdin" => options.stdin = true,
+            "-a" | "--ascii" => options.ascii = true,
+            "--interactive" => options.interactive = true,
+            "-p" | "--patch" => {
+                let patch = value(&arg)?;
+                let (address, v) = match patch.find('=') {
+                    Some(pos) => (&patch[..pos], &patch[pos + 1..]),
+                    None => return Err(format!("patch {:?} should look like <addr>=<value>", patch)),
+                };
+                options.patches.push((number(address, "address")?, number(v, "value")?));
+            }
+            "-r" | "--read" => options.reads.push(number(&value(&arg)?, "address")?),
+            "-t" | "--trace" => options.trace = true,
+            "--trace-json" => options.trace_json = Some(value(&arg)?),
+            "-s" | "--max-steps" => options.max_steps = Some(number(&value(&arg)?, "step count")?),
+            "--profile" => options.profile = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') && arg.parse::<i64>().is_err() => return Err(format!("unknown option {:?}", arg)),
+            _ if program.is_none() => program = Some(arg),
+            _ => options.inputs.push(number(&arg, "input")?),
+        }
+    }
+    options.program = program.ok_or("missing program file")?;
+    if options.interactive && (options.stdin || options.trace || options.trace_json.is_some()) {
+        return Err("--interactive cannot be combined with --stdin or tracing".to_string());
+    }
+    Ok(options)
+}
+
+fn read_inputs(filename: &str) -> Result<Vec<i64>, String> {
+    let text = fs::read_to_string(filename).map_err(|e| format!("error reading {}: {}", filename, e))?;
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .map(|word| number(word, "input"))
+        .collect()
+}
+
+/// The instruction at the instruction pointer, as the trace shows it.
+fn trace_line(machine: &Machine) -> String {
+    let index = machine.index();
+    let words: Vec<i64> = (index..index + 4).map(|a| machine.read(a)).collect();
+    let text = match decode(&words, 0) {
+        Some(decoded) => decoded.to_string(),
+        None => format!("data {}", words[0]),
+    };
+    format!("{:>6}  {:<32} rb={}", index, text, machine.relative_base())
+}
+
+/// Runs the program as `options` say, reading typed input from `input` and
+/// writing output and `--read` values to `out`.
+fn run<R: BufRead, T: Write>(options: &Options, input: R, mut out: T) -> Result<(), String> {
+    let program = read_program(&options.program).map_err(|e| format!("error reading {}: {}", options.program, e))?;
+    let mut inputs: VecDeque<i64> = options.inputs.iter().cloned().collect();
+    for filename in &options.input_files {
+        inputs.extend(read_inputs(filename)?);
+    }
+
+    let mut machine = Machine::new(&program);
+    for (address, value) in &options.patches {
+        machine.write(*address, *value).map_err(|e| format!("cannot patch {}: {}", address, e))?;
+    }
+    machine.set_budget(options.max_steps);
+    if options.profile {
+        machine.enable_profiling();
+    }
+    let mut trace = match &options.trace_json {
+        Some(filename) => {
+            machine.enable_tracing();
+            let file = fs::File::create(filename).map_err(|e| format!("error creating {}: {}", filename, e))?;
+            Some(BufWriter::new(file))
+        }
+        None => None,
+    };
+
+    let result = if options.interactive {
+        for value in inputs {
+            machine.push_input(value);
+        }
+        let mut ascii = Ascii::new(machine);
+        let state = ascii.interact(input, &mut out);
+        machine = ascii.into_machine();
+        match state {
+            Ok(State::Halted) => Ok(()),
+            Ok(_) => Err("the input ended before the program halted".to_string()),
+            Err(e) => Err(format!("error: {}", e)),
+        }
+    } else {
+        let mut stdin = LineInput::new(input);
+        loop {
+            if machine.is_halted() {
+                break Ok(());
+            }
+            if machine.pending_input() == 0 && machine.read(machine.index()) % 100 == 3 {
+                let value = inputs.pop_front().or_else(|| if options.stdin { stdin.read() } else { None });
+                for line in stdin.take_skipped() {
+                    eprintln!("not a number: {:?}", line);
+                }
+                match value {
+                    Some(value) => machine.push_input(value),
+                    None => break Err(format!("the program needs more input at {}", machine.index())),
+                }
+            }
+            if options.trace {
+                eprintln!("{}", trace_line(&machine));
+            }
+            let stepped = machine.step();
+            if let Some(trace) = trace.as_mut() {
+                for record in machine.take_trace() {
+                    writeln!(trace, "{}", record).map_err(|e| format!("error writing trace: {}", e))?;
+                }
+            }
+            if let Err(e) = stepped {
+                break Err(format!("error: {}", e));
+            }
+            for value in machine.take_output() {
+                let written = match value {
+                    0..=127 if options.ascii => write!(out, "{}", value as u8 as char),
+                    _ => writeln!(out, "{}", value),
+                };
+                written.and_then(|_| out.flush()).map_err(|e| format!("error writing output: {}", e))?;
+            }
+        }
+    };
+    if let Some(trace) = trace.as_mut() {
+        trace.flush().map_err(|e| format!("error writing trace: {}", e))?;
+    }
+    if let Some(profile) = machine.profile() {
+        eprintln!("{}", profile.report(&program, 20));
+    }
+    result?;
+    for address in &options.reads {
+        writeln!(out, "{}", machine.read(*address)).map_err(|e| format!("error writing output: {}", e))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if message.is_empty() {
+                println!("{}", USAGE);
+                return;
+            }
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(message) = run(&options, io::stdin().lock(), io::stdout().lock()) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode::assemble;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse("-p 1=12 --patch 2=2 -r 0 -a day2/data.txt 5 -3 -s 100 --trace-json t.jsonl").unwrap();
+        assert_eq!(options.program, "day2/data.txt");
+        assert_eq!(options.patches, vec![(1, 12), (2, 2)]);
+        assert_eq!(options.reads, vec![0]);
+        assert_eq!(options.inputs, vec![5, -3]);
+        assert_eq!(options.max_steps, Some(100));
+        assert_eq!(options.trace_json, Some("t.jsonl".to_string()));
+        assert!(options.ascii && !options.trace && !options.stdin && !options.interactive);
+        assert!(parse("--interactive day25/data.txt").unwrap().interactive);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("-p 1 data.txt").unwrap_err(), "patch \"1\" should look like <addr>=<value>");
+        assert_eq!(parse("--frobnicate data.txt").unwrap_err(), "unknown option \"--frobnicate\"");
+        assert_eq!(parse("-s").unwrap_err(), "-s needs a value");
+        assert_eq!(parse("data.txt x").unwrap_err(), "invalid input \"x\"");
+        assert_eq!(parse("").unwrap_err(), "missing program file");
+        assert_eq!(parse("--help").unwrap_err(), "");
+        let error = parse("--interactive -t data.txt").unwrap_err();
+        assert_eq!(error, "--interactive cannot be combined with --stdin or tracing");
+    }
+
+    /// A file in the temp directory holding `text`, unique to this process
+    /// and `name`.
+    fn temp_file(name: &str, text: &str) -> String {
+        let path = env::temp_dir().join(format!("intcode-cli-{}-{}.txt", process::id(), name));
+        fs::write(&path, text).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Assembles `source` into a program file and runs it with `args`,
+    /// options and inputs, after the file name, typing `stdin`. Returns the result and what was printed.
+    fn run_source(name: &str, source: &str, args: &str, stdin: &str) -> (Result<(), String>, String) {
+        let program: Vec<String> = assemble(source).unwrap().iter().map(i64::to_string).collect();
+        let path = temp_file(name, &program.join(","));
+        let options = parse(&format!("{} {}", path, args)).unwrap();
+        let mut out = Vec::new();
+        let result = run(&options, stdin.as_bytes(), &mut out);
+        fs::remove_file(path).unwrap();
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    // Multiplies x and y into z and outputs it.
+    const PRODUCT: &str = "mul [x], [y], [z]\nout [z]\nhlt\nx: data 3\ny: data 4\nz: data 0";
+
+    #[test]
+    fn test_run_patch_and_read() {
+        assert_eq!(run_source("plain", PRODUCT, "", ""), (Ok(()), "12\n".to_string()));
+        assert_eq!(run_source("patch", PRODUCT, "-p 7=5 -r 9 -r 7", ""), (Ok(()), "20\n20\n5\n".to_string()));
+        let (result, out) = run_source("bad-patch", PRODUCT, "-p -1=5", "");
+        assert_eq!((result.unwrap_err().as_str(), out.as_str()), ("cannot patch -1: negative address -1", ""));
+    }
+
+    #[test]
+    fn test_run_ascii() {
+        let source = "out #72\nout #105\nout #10\nout #1000\nhlt";
+        assert_eq!(run_source("ascii", source, "-a", ""), (Ok(()), "Hi\n1000\n".to_string()));
+        assert_eq!(run_source("numbers", source, "", ""), (Ok(()), "72\n105\n10\n1000\n".to_string()));
+    }
+
+    #[test]
+    fn test_run_inputs() {
+        let source = "in [x]\nin [y]\nadd [x], [y], [x]\nout [x]\nhlt\nx: data 0\ny: data 0";
+        let inputs = temp_file("inputs", "3,\n 4\n");
+        let (result, out) = run_source("input-file", source, &format!("-i {}", inputs), "");
+        fs::remove_file(inputs).unwrap();
+        assert_eq!((result, out.as_str()), (Ok(()), "7\n"));
+        assert_eq!(run_source("stdin", source, "--stdin 3", "x\n4\n"), (Ok(()), "7\n".to_string()));
+        let (result, _) = run_source("no-input", source, "3", "4\n");
+        assert_eq!(result.unwrap_err(), "the program needs more input at 2");
+    }
+
+    #[test]
+    fn test_run_step_cap() {
+        let source = "out #1\nloop: jnz #1, #loop";
+        let (result, out) = run_source("capped", source, "-s 10", "");
+        assert_eq!(result.unwrap_err(), "error: step budget exhausted at 2 (instruction 1105)");
+        assert_eq!(out, "1\n");
+        // Reads are only printed after the program halts.
+        let (result, out) = run_source("capped-read", source, "-s 10 -r 0", "");
+        assert!(result.is_err());
+        assert_eq!(out, "1\n");
+    }
+}
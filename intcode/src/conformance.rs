@@ -0,0 +1,87 @@
+//! Example programs with the output and final memory every Intcode
+//! interpreter must agree on, shared by the tests of this crate and the
+//! days. The cases live in `conformance/corpus.txt`.
+
+use crate::parse::parse_program;
+
+const CORPUS: &str = include_str!("../conformance/corpus.txt");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+    /// Memory when the program halts, without trailing zeros.
+    pub memory: Vec<i64>,
+}
+
+impl Case {
+    /// Whether `memory` is the memory the case expects. Zeros past the end
+    /// are ignored, since interpreters differ in how far memory grows.
+    pub fn memory_matches(&self, memory: &[i64]) -> bool {
+        let end = memory.iter().rposition(|word| *word != 0).map_or(0, |last| last + 1);
+        memory[..end] == self.memory[..]
+    }
+}
+
+/// All cases in the corpus, in the order they are listed.
+pub fn corpus() -> Vec<Case> {
+    parse_corpus(CORPUS).unwrap_or_else(|e| panic!("conformance/corpus.txt: {}", e))
+}
+
+fn parse_corpus(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases = Vec::new();
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    while let Some((number, line)) = lines.next() {
+        let name = match line.strip_prefix("case ") {
+            Some(name) => name.trim().to_string(),
+            None => return Err(format!("line {}: expected `case <name>`", number)),
+        };
+        let mut field = |key: &str| -> Result<Vec<i64>, String> {
+            let (number, line) = lines.next().ok_or(format!("case {}: missing `{}`", name, key))?;
+            let words = match line.strip_prefix(key) {
+                Some(words) if words.is_empty() || words.starts_with(' ') => words,
+                _ => return Err(format!("line {}: expected `{}`", number, key)),
+            };
+            if words.is_empty() {
+                return Ok(Vec::new());
+            }
+            parse_program(words).map_err(|e| format!("line {}: {}", number, e))
+        };
+        let (program, input, output, memory) = (field("program")?, field("input")?, field("output")?, field("memory")?);
+        cases.push(Case { name, program, input, output, memory });
+    }
+    Ok(cases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corpus() {
+        let cases = corpus();
+        assert!(cases.len() > 20);
+        let echo = cases.iter().find(|case| case.name == "day5-echo").unwrap();
+        assert_eq!((&echo.input[..], &echo.output[..]), (&[42][..], &[42][..]));
+        assert!(echo.memory_matches(&[42, 0, 4, 0, 99, 0, 0]));
+        assert!(!echo.memory_matches(&[42, 0, 4, 0]));
+        let mut names: Vec<_> = cases.iter().map(|case| &case.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), cases.len());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_corpus("program 1"), Err("line 1: expected `case <name>`".to_string()));
+        assert_eq!(parse_corpus("case a\nprogram 99\ninput\n"), Err("case a: missing `output`".to_string()));
+        assert_eq!(parse_corpus("case a\nprogram 99\noutputs"), Err("line 3: expected `input`".to_string()));
+        assert!(parse_corpus("case a\nprogram 99,,\ninput\noutput\nmemory 99").unwrap_err().starts_with("line 2: "));
+    }
+}
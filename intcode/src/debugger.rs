@@ -0,0 +1,597 @@
+//! Interactive step debugger for Intcode programs.
+//!
+//! Commands (type `help` in the REPL for the same list):
+//!
+//! ```text
+//! step [n]            execute n instructions (default 1)
+//! record [n]          keep an undo log of the last n instructions for back
+//!                     (default 1000000); `record off` stops it
+//! back [n]            undo the last n instructions (default 1)
+//! back write <addr>   undo back to just before the last write to addr
+//! continue            run until a breakpoint, input is needed or the program
+//!                     halts, or for at most 10000000 instructions
+//! break <addr>        break before executing the instruction at addr
+//! break op <code>     break before executing any instruction with this opcode
+//! delete <addr>       remove an address breakpoint (`delete op <code>` for opcodes)
+//! watch <addr> [n]    stop after writes to n addresses from addr (default 1)
+//! watch self          stop after writes to code that ran or is about to run
+//! delete watch        remove all write watchpoints
+//! breakpoints         list breakpoints and watchpoints
+//! regs                show instruction pointer, relative base and pending input
+//! mem <addr> [count]  dump count words of memory starting at addr, in at
+//!                     most 1000 lines
+//! set <addr> <value>  write value to memory
+//! input <v>...        queue input values
+//! list [addr] [n]     disassemble n instructions from addr (default: ip),
+//!                     at most 1000
+//! save <file>         save the machine state to a snapshot file
+//! load <file>         replace the machine state with a saved snapshot, keeping
+//!                     breakpoints and watchpoints
+//! quit
+//! ```
+
+use std::collections::BTreeSet;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+
+use crate::disasm::{decode, Decoded, Line};
+use crate::machine::Machine;
+use crate::snapshot::Snapshot;
+
+const HELP: &str = "commands: step [n], record [n], record off, back [n], back write <addr>, continue, break <addr>, break op <code>, \
+delete <addr>, delete op <code>, watch <addr> [n], watch self, delete watch, breakpoints, regs, mem <addr> [count], \
+set <addr> <value>, input <v>..., list [addr] [n], save <file>, load <file>, quit";
+
+/// How many instructions `back` can undo after `record` without a count.
+const HISTORY: usize = 1_000_000;
+
+/// How many instructions `continue` runs before handing back the prompt.
+const CONTINUE_LIMIT: u64 = 10_000_000;
+
+/// How many lines `mem` and `list` print at most.
+const MAX_LINES: usize = 1_000;
+
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: BTreeSet<i64>,
+    opcode_breakpoints: BTreeSet<i64>,
+    /// Length of the undo log, when recording. The log slows every step
+    /// down, so it is off until asked for.
+    record: Option<usize>,
+    continue_limit: u64,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine) -> Debugger {
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: BTreeSet::new(),
+            record: None,
+            continue_limit: CONTINUE_LIMIT,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    fn waiting_for_input(&self) -> bool {
+        self.machine.pending_input() == 0 && self.opcode() == 3
+    }
+
+    fn opcode(&self) -> i64 {
+        self.machine.read(self.machine.index()) % 100
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.breakpoints.contains(&self.machine.index()) || self.opcode_breakpoints.contains(&self.opcode())
+    }
+
+    /// Executes one instruction, or explains why it cannot.
+    fn step_once(&mut self, report: &mut Vec<String>) -> bool {
+        if self.machine.is_halted() {
+            report.push("program has halted".to_string());
+            return false;
+        }
+        if self.waiting_for_input() {
+            report.push(format!("waiting for input at {}", self.machine.index()));
+            return false;
+        }
+        let result = self.machine.step();
+        for value in self.machine.take_output() {
+            report.push(format!("output: {}", value));
+        }
+        let hits = self.machine.take_write_hits();
+        for hit in &hits {
+            report.push(format!("write: {}", hit));
+        }
+        match result {
+            Ok(()) => hits.is_empty(),
+            Err(error) => {
+                report.push(format!("error: {}", error));
+                false
+            }
+        }
+    }
+
+    fn current_line(&self) -> String {
+        self.line_at(self.machine.index()).0
+    }
+
+    fn line_at(&self, address: i64) -> (String, i64) {
+        let start = address.max(0);
+        let words: Vec<i64> = (0..4).map(|i| self.machine.read(start.saturating_add(i))).collect();
+        match decode(&words, 0) {
+            Some(decoded) => {
+                let size = match &decoded {
+                    Decoded::Instruction { operands, .. } => 1 + operands.len(),
+                    Decoded::Data => 1,
+                };
+                let line = Line {
+                    address: start as usize,
+                    words: words[..size].to_vec(),
+                    decoded,
+                    jump_target: false,
+                };
+                (line.to_string(), size as i64)
+            }
+            None => {
+                let line = Line {
+                    address: start as usize,
+                    words: vec![words[0]],
+                    decoded: Decoded::Data,
+                    jump_target: false,
+                };
+                (line.to_string(), 1)
+            }
+        }
+    }
+
+    fn step(&mut self, count: usize) -> Vec<String> {
+        let mut report = Vec::new();
+        for _ in 0..count {
+            if !self.step_once(&mut report) {
+                return report;
+            }
+        }
+        if self.machine.is_halted() {
+            report.push("program has halted".to_string());
+        } else {
+            report.push(self.current_line());
+        }
+        report
+    }
+
+    fn resume(&mut self) -> Vec<String> {
+        let mut report = Vec::new();
+        if !self.step_once(&mut report) {
+            return report;
+        }
+        let mut steps = 1;
+        while !self.machine.is_halted() && !self.waiting_for_input() && !self.at_breakpoint() {
+            if steps == self.continue_limit {
+                report.push(format!("stopped after {} steps", steps));
+                report.push(self.current_line());
+                return report;
+            }
+            if !self.step_once(&mut report) {
+                return report;
+            }
+            steps += 1;
+        }
+        if self.machine.is_halted() {
+            report.push("program has halted".to_string());
+        } else if self.at_breakpoint() {
+            report.push(format!("breakpoint at {}", self.machine.index()));
+            report.push(self.current_line());
+        } else {
+            report.push(format!("waiting for input at {}", self.machine.index()));
+        }
+        report
+    }
+
+    fn record(&mut self, limit: Option<usize>) -> Vec<String> {
+        self.record = limit;
+        match limit {
+            Some(limit) => {
+                self.machine.enable_history(limit);
+                vec![format!("recording the last {} instruction(s)", limit)]
+            }
+            None => {
+                self.machine.disable_history();
+                vec!["stopped recording".to_string()]
+            }
+        }
+    }
+
+    fn back(&mut self, steps: usize) -> Vec<String> {
+        if self.record.is_none() {
+            return vec!["not recording, type record to keep an undo log".to_string()];
+        }
+        match self.machine.rewind(steps) {
+            0 => vec!["nothing to undo".to_string()],
+            undone => vec![format!("undid {} instruction(s)", undone), self.current_line()],
+        }
+    }
+
+    fn back_to_write(&mut self, address: i64) -> Vec<String> {
+        if self.record.is_none() {
+            return vec!["not recording, type record to keep an undo log".to_string()];
+        }
+        match self.machine.rewind_to_write(address) {
+            Some(undone) => vec![format!("undid {} instruction(s)", undone), self.current_line()],
+            None => vec![format!("no recorded write to {}", address)],
+        }
+    }
+
+    fn watch(&mut self, address: i64, count: i64) -> Vec<String> {
+        let end = match address.checked_add(count) {
+            Some(end) => end,
+            None => return vec![format!("the range from {} is too long", address)],
+        };
+        self.machine.watch_writes(address..end);
+        vec![format!("watching writes to {}..{}", address, end)]
+    }
+
+    fn regs(&self) -> String {
+        format!(
+            "ip: {}  relative base: {}  pending input: {}{}",
+            self.machine.index(),
+            self.machine.relative_base(),
+            self.machine.pending_input(),
+            if self.machine.is_halted() { "  (halted)" } else { "" }
+        )
+    }
+
+    fn memory(&self, start: i64, count: i64) -> Vec<String> {
+        let last = match start.checked_add(count) {
+            Some(last) => last,
+            None => return vec![format!("the range from {} is too long", start)],
+        };
+        let mut lines = Vec::new();
+        let mut address = start;
+        while address < last {
+            if lines.len() == MAX_LINES {
+                lines.push(format!("(cut short after {} lines)", MAX_LINES));
+                break;
+            }
+            let end = address.saturating_add(10).min(last);
+            let words: Vec<String> = (address..end).map(|a| self.machine.read(a).to_string()).collect();
+            lines.push(format!("{:>6}: {}", address, words.join(" ")));
+            address = end;
+        }
+        lines
+    }
+
+    fn list(&self, start: i64, count: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut address = start;
+        for _ in 0..count {
+            if lines.len() == MAX_LINES {
+                lines.push(format!("(cut short after {} lines)", MAX_LINES));
+                break;
+            }
+            let (line, size) = self.line_at(address);
+            lines.push(line);
+            address = match address.checked_add(size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        lines
+    }
+
+    /// Runs a single debugger command and returns what it printed.
+    /// `None` means the user asked to quit.
+    pub fn execute(&mut self, command: &str) -> Option<String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["watch", "self"] => {
+                self.machine.detect_self_modification();
+                return Some("watching for self-modifying writes".to_string());
+            }
+            ["record", "off"] => return Some(self.record(None).join("\n")),
+            ["delete", "watch"] | ["d", "watch"] => {
+                self.machine.clear_watchpoints();
+                return Some("deleted watchpoints".to_string());
+            }
+            ["save", file] => {
+                return Some(match self.machine.snapshot().save(file) {
+                    Ok(()) => format!("saved to {}", file),
+                    Err(e) => format!("error saving {}: {}", file, e),
+                })
+            }
+            ["load", file] => {
+                return Some(match Snapshot::load(file) {
+                    Ok(snapshot) => {
+                        let watchpoints = self.machine.watchpoints();
+                        let self_modification = self.machine.detects_self_modification();
+                        self.machine = Machine::from_snapshot(snapshot);
+                        if let Some(limit) = self.record {
+                            self.machine.enable_history(limit);
+                        }
+                        for range in watchpoints {
+                            self.machine.watch_writes(range);
+                        }
+                        if self_modification {
+                            self.machine.detect_self_modification();
+                        }
+                        self.current_line()
+                    }
+                    Err(e) => format!("error loading {}: {}", file, e),
+                })
+            }
+            _ => {}
+        }
+        let numbers: Result<Vec<i64>, _> = words.iter().skip(1).filter(|w| **w != "op" && **w != "write").map(|w| w.parse::<i64>()).collect();
+        let numbers = match numbers {
+            Ok(numbers) => numbers,
+            Err(_) => return Some(format!("invalid number in {:?}", command)),
+        };
+        let opcode = words.get(1) == Some(&"op");
+        let write = words.get(1) == Some(&"write");
+        let lines = match (words.first().cloned().unwrap_or(""), numbers.as_slice()) {
+            ("", _) => Vec::new(),
+            ("quit", _) | ("q", _) => return None,
+            ("help", _) | ("h", _) => vec![HELP.to_string()],
+            ("step", []) | ("s", []) => self.step(1),
+            ("step", [n]) | ("s", [n]) if *n > 0 => self.step(*n as usize),
+            ("record", []) => self.record(Some(HISTORY)),
+            ("record", [n]) if *n > 0 => self.record(Some(*n as usize)),
+            ("back", [address]) if write => self.back_to_write(*address),
+            ("back", []) => self.back(1),
+            ("back", [n]) if *n > 0 => self.back(*n as usize),
+            ("continue", []) | ("c", []) => self.resume(),
+            ("break", [n]) | ("b", [n]) => {
+                if opcode {
+                    self.opcode_breakpoints.insert(*n);
+                    vec![format!("breakpoint on opcode {}", n)]
+                } else {
+                    self.breakpoints.insert(*n);
+                    vec![format!("breakpoint at {}", n)]
+                }
+            }
+            ("delete", [n]) | ("d", [n]) => {
+                let removed = if opcode {
+                    self.opcode_breakpoints.remove(n)
+                } else {
+                    self.breakpoints.remove(n)
+                };
+                if removed {
+                    vec![format!("deleted breakpoint {}", n)]
+                } else {
+                    vec![format!("no breakpoint {}", n)]
+                }
+            }
+            ("watch", [address]) => self.watch(*address, 1),
+            ("watch", [address, count]) if *count > 0 => self.watch(*address, *count),
+            ("breakpoints", []) => {
+                let mut lines: Vec<String> = self.breakpoints.iter().map(|a| format!("address {}", a)).collect();
+                lines.extend(self.opcode_breakpoints.iter().map(|c| format!("opcode {}", c)));
+                lines.extend(self.machine.watchpoints().iter().map(|r| format!("writes to {}..{}", r.start, r.end)));
+                lines
+            }
+            ("regs", []) | ("r", []) => vec![self.regs()],
+            ("mem", [start]) | ("m", [start]) => self.memory(*start, 10),
+            ("mem", [start, count]) | ("m", [start, count]) => self.memory(*start, *count),
+            ("set", [address, value]) => match self.machine.write(*address, *value) {
+                Ok(()) => vec![format!("{} = {}", address, value)],
+                Err(kind) => vec![format!("error: {}", kind)],
+            },
+            ("input", values) | ("i", values) if !values.is_empty() => {
+                for value in values {
+                    self.machine.push_input(*value);
+                }
+                vec![format!("pending input: {}", self.machine.pending_input())]
+            }
+            ("list", []) | ("l", []) => self.list(self.machine.index(), 10),
+            ("list", [start]) | ("l", [start]) => self.list(*start, 10),
+            ("list", [start, count]) | ("l", [start, count]) => self.list(*start, (*count).max(0) as usize),
+            _ => vec![format!("unknown command {:?}, type help for a list", command.trim())],
+        };
+        Some(lines.join("\n"))
+    }
+
+    /// Reads commands from `input` until `quit` or end of input.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", self.current_line())?;
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.execute(&line?) {
+                Some(text) => {
+                    if !text.is_empty() {
+                        writeln!(output, "{}", text)?;
+                    }
+                }
+                None => return Ok(()),
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn debugger(source: &str) -> Debugger {
+        Debugger::new(Machine::new(&assemble(source).unwrap()))
+    }
+
+    fn run(debugger: &mut Debugger, command: &str) -> String {
+        debugger.execute(command).unwrap()
+    }
+
+    #[test]
+    fn test_step_and_regs() {
+        let mut debugger = debugger("arb #5\nout #7\nhlt");
+        assert_eq!(run(&mut debugger, "step"), "     2  104,7                       out #7");
+        assert_eq!(run(&mut debugger, "regs"), "ip: 2  relative base: 5  pending input: 0");
+        assert!(run(&mut debugger, "s").starts_with("output: 7\n"));
+        assert_eq!(run(&mut debugger, "step"), "program has halted");
+        assert_eq!(run(&mut debugger, "step"), "program has halted");
+        assert!(run(&mut debugger, "r").ends_with("(halted)"));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger(
+            "loop: add [n], #1, [n]
+                   out [n]
+                   lt [n], #3, [flag]
+                   jnz [flag], #loop
+                   hlt
+             n:    data 0
+             flag: data 0",
+        );
+        assert_eq!(run(&mut debugger, "break 4"), "breakpoint at 4");
+        let report = run(&mut debugger, "continue");
+        assert!(report.starts_with("breakpoint at 4"));
+        assert_eq!(run(&mut debugger, "c"), "output: 1\nbreakpoint at 4\n     4  4,14                        out [14]");
+        assert_eq!(run(&mut debugger, "delete 4"), "deleted breakpoint 4");
+        assert_eq!(run(&mut debugger, "break op 5"), "breakpoint on opcode 5");
+        assert_eq!(run(&mut debugger, "breakpoints"), "opcode 5");
+        assert!(run(&mut debugger, "c").starts_with("output: 2\nbreakpoint at 10"));
+        assert_eq!(run(&mut debugger, "d op 5"), "deleted breakpoint 5");
+        assert_eq!(run(&mut debugger, "c"), "output: 3\nprogram has halted");
+    }
+
+    #[test]
+    fn test_memory_and_input() {
+        let mut debugger = debugger("in [x]\nout [x]\nhlt\nx: data 0");
+        assert_eq!(run(&mut debugger, "step"), "waiting for input at 0");
+        assert_eq!(run(&mut debugger, "input 5 6"), "pending input: 2");
+        run(&mut debugger, "step");
+        assert_eq!(run(&mut debugger, "mem 5 2"), "     5: 5 0");
+        assert_eq!(run(&mut debugger, "set 5 42"), "5 = 42");
+        assert_eq!(run(&mut debugger, "c"), "output: 42\nprogram has halted");
+        assert_eq!(run(&mut debugger, "regs"), "ip: 4  relative base: 0  pending input: 1  (halted)");
+    }
+
+    #[test]
+    fn test_list_and_errors() {
+        let mut debugger = debugger("in [x]\nhlt\nx: data 0");
+        assert_eq!(
+            run(&mut debugger, "list 0 3"),
+            "     0  3,3                         in [3]\n     2  99                          hlt\n     3  0                           data 0"
+        );
+        assert!(run(&mut debugger, "frobnicate").starts_with("unknown command"));
+        assert!(run(&mut debugger, "mem x").starts_with("invalid number"));
+        let max = i64::MAX;
+        assert_eq!(run(&mut debugger, &format!("mem {} 2", max)), format!("the range from {} is too long", max));
+        assert_eq!(run(&mut debugger, &format!("watch {}", max)), format!("the range from {} is too long", max));
+        assert!(run(&mut debugger, &format!("list {} 3", max - 1)).ends_with("data 0"));
+        assert_eq!(run(&mut debugger, "set -1 42"), "error: negative address -1");
+        run(&mut debugger, "set 0 42");
+        assert_eq!(run(&mut debugger, "c"), "error: unknown opcode 42 at 0 (instruction 42)");
+        assert_eq!(run(&mut debugger, "regs"), "ip: 0  relative base: 0  pending input: 0");
+        assert_eq!(debugger.execute("quit"), None);
+    }
+
+    #[test]
+    fn test_back() {
+        let mut debugger = debugger("in [x]\nadd [x], #1, [x]\nout [x]\nhlt\nx: data 0");
+        assert_eq!(run(&mut debugger, "back"), "not recording, type record to keep an undo log");
+        assert_eq!(run(&mut debugger, "record"), "recording the last 1000000 instruction(s)");
+        assert_eq!(run(&mut debugger, "back"), "nothing to undo");
+        run(&mut debugger, "input 4");
+        assert_eq!(run(&mut debugger, "c"), "output: 5\nprogram has halted");
+        assert_eq!(run(&mut debugger, "back 2"), "undid 2 instruction(s)\n     6  4,9                         out [9]");
+        assert_eq!(run(&mut debugger, "back write 9"), "undid 1 instruction(s)\n     2  1001,9,1,9                  add [9], #1, [9]");
+        assert_eq!(run(&mut debugger, "mem 9 1"), "     9: 4");
+        assert_eq!(run(&mut debugger, "back write 3"), "no recorded write to 3");
+        assert_eq!(run(&mut debugger, "c"), "output: 5\nprogram has halted");
+        assert_eq!(run(&mut debugger, "record off"), "stopped recording");
+        assert!(debugger.machine().history().is_empty());
+        assert_eq!(run(&mut debugger, "back write 9"), "not recording, type record to keep an undo log");
+    }
+
+    #[test]
+    fn test_record_limit() {
+        let mut debugger = debugger("out #1\nout #2\nout #3\nhlt");
+        run(&mut debugger, "step");
+        assert!(debugger.machine().history().is_empty());
+        assert_eq!(run(&mut debugger, "record 1"), "recording the last 1 instruction(s)");
+        run(&mut debugger, "step 2");
+        assert_eq!(run(&mut debugger, "back 5"), "undid 1 instruction(s)\n     4  104,3                       out #3");
+    }
+
+    #[test]
+    fn test_continue_limit() {
+        let mut debugger = debugger("loop: jz #0, #loop");
+        debugger.continue_limit = 100;
+        assert_eq!(run(&mut debugger, "c"), "stopped after 100 steps\n     0  1106,0,0                    jz #0, #0");
+        assert_eq!(run(&mut debugger, "regs"), "ip: 0  relative base: 0  pending input: 0");
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let mut debugger = debugger("hlt");
+        let lines: Vec<String> = run(&mut debugger, &format!("mem 0 {}", i64::MAX)).lines().map(String::from).collect();
+        assert_eq!(lines.len(), MAX_LINES + 1);
+        assert_eq!(lines[MAX_LINES], "(cut short after 1000 lines)");
+        let lines: Vec<String> = run(&mut debugger, &format!("list 0 {}", i64::MAX)).lines().map(String::from).collect();
+        assert_eq!(lines.len(), MAX_LINES + 1);
+        assert_eq!(lines[MAX_LINES], "(cut short after 1000 lines)");
+        assert_eq!(run(&mut debugger, "list 0 1000").lines().count(), MAX_LINES);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = debugger(
+            "loop: add [n], #1, [n]
+                   lt [n], #3, [flag]
+                   jnz [flag], #loop
+                   hlt
+             n:    data 0
+             flag: data 0",
+        );
+        assert_eq!(run(&mut debugger, "watch 13"), "watching writes to 13..14");
+        assert_eq!(run(&mut debugger, "c"), "write: 4: lt [12], #3, [13] wrote 1 to 13 (was 0), watchpoint");
+        assert_eq!(run(&mut debugger, "regs"), "ip: 8  relative base: 0  pending input: 0");
+        assert_eq!(run(&mut debugger, "breakpoints"), "writes to 13..14");
+        assert_eq!(run(&mut debugger, "delete watch"), "deleted watchpoints");
+        assert_eq!(run(&mut debugger, "c"), "program has halted");
+
+        let mut debugger = Debugger::new(Machine::new(&[1002, 4, 3, 4, 33]));
+        run(&mut debugger, "watch self");
+        assert!(run(&mut debugger, "step 5").starts_with("write: 0: mul [4], #3, [4] wrote 99 to 4 (was 33)"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut debugger = debugger("in [x]\nout [x]\nhlt\nx: data 0");
+        let path = std::env::temp_dir().join(format!("intcode-debugger-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        run(&mut debugger, "input 9");
+        run(&mut debugger, "step");
+        assert_eq!(run(&mut debugger, &format!("save {}", path)), format!("saved to {}", path));
+        assert_eq!(run(&mut debugger, "c"), "output: 9\nprogram has halted");
+        run(&mut debugger, "watch 5");
+        run(&mut debugger, "watch self");
+        run(&mut debugger, "record 10");
+        assert!(run(&mut debugger, &format!("load {}", path)).ends_with("out [5]"));
+        assert_eq!(run(&mut debugger, "breakpoints"), "writes to 5..6");
+        assert!(debugger.machine().detects_self_modification());
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(run(&mut debugger, "c"), "output: 9\nprogram has halted");
+        assert_eq!(debugger.machine().history().len(), 2);
+        assert!(run(&mut debugger, "load /nonexistent/snapshot").starts_with("error loading"));
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut debugger = debugger("out #1\nhlt");
+        let mut output = Vec::new();
+        debugger.repl("step\nquit\nstep\n".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("output: 1").count(), 1);
+        assert!(!debugger.machine().is_halted());
+    }
+}
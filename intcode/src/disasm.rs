@@ -0,0 +1,339 @@
+//! Disassembler producing annotated listings of Intcode programs.
+//!
+//! Decoding starts at address 0 and follows fall-through and immediate jump
+//! targets, so only words reachable as instructions are listed as code and
+//! everything else is listed as `data`. Operands use the assembler syntax.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::asm::{write_operand, MNEMONICS};
+use crate::machine::{parse_instruction, Mode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operand {
+    pub mode: Mode,
+    pub value: i64,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "[rb{}]", self.value),
+            Mode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Instruction {
+        code: i64,
+        mnemonic: &'static str,
+        operands: Vec<Operand>,
+    },
+    Data,
+}
+
+/// One line of a listing: an instruction or a single data word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub words: Vec<i64>,
+    pub decoded: Decoded,
+    /// Whether some decoded jump lands here.
+    pub jump_target: bool,
+}
+
+/// Instructions display in assembler syntax; data has no text of its own.
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoded::Instruction { mnemonic, operands, .. } => {
+                write!(f, "{}", mnemonic)?;
+                let operands = operands.iter().map(|o| o.to_string()).collect::<Vec<_>>();
+                if !operands.is_empty() {
+                    write!(f, " {}", operands.join(", "))?;
+                }
+                Ok(())
+            }
+            Decoded::Data => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = self.words.iter().map(|w| w.to_string()).collect::<Vec<_>>().join(",");
+        write!(f, "{:>6}  {:<28}", self.address, words)?;
+        match &self.decoded {
+            Decoded::Instruction { .. } => write!(f, "{}", self.decoded),
+            Decoded::Data => write!(f, "data {}", self.words[0]),
+        }
+    }
+}
+
+/// Decodes the instruction at `address`, or `None` if the word there is not
+/// a valid instruction or runs past the end of the program.
+pub fn decode(program: &[i64], address: usize) -> Option<Decoded> {
+    let word = *program.get(address)?;
+    if word < 0 {
+        return None;
+    }
+    let (mnemonic, code, count) = *MNEMONICS.iter().find(|(_, code, _)| *code == word % 100)?;
+    let mode_digits = word / 100;
+    if mode_digits >= 10_i64.pow(count as u32) || address + count >= program.len() {
+        return None;
+    }
+    let instruction = parse_instruction(word).ok()?;
+    let operands: Vec<Operand> = (0..count)
+        .map(|i| Operand {
+            mode: instruction.modes[i],
+            value: program[address + 1 + i],
+        })
+        .collect();
+    if let Some(position) = write_operand(code) {
+        if operands[position - 1].mode == Mode::Immediate {
+            return None;
+        }
+    }
+    Some(Decoded::Instruction { code, mnemonic, operands })
+}
+
+fn decoded_size(decoded: &Decoded) -> usize {
+    match decoded {
+        Decoded::Instruction { operands, .. } => 1 + operands.len(),
+        Decoded::Data => 1,
+    }
+}
+
+/// Addresses execution can continue at after the instruction at `address`,
+/// and the target of the jump if it is known without running the program.
+pub(crate) fn successors(address: usize, decoded: &Decoded) -> (Option<usize>, Option<i64>) {
+    let (code, operands) = match decoded {
+        Decoded::Instruction { code, operands, .. } => (*code, operands),
+        Decoded::Data => return (None, None),
+    };
+    let next = address + 1 + operands.len();
+    match code {
+        99 => (None, None),
+        5 | 6 => {
+            let condition = operands[0];
+            let always = condition.mode == Mode::Immediate && (condition.value != 0) == (code == 5);
+            let never = condition.mode == Mode::Immediate && !always;
+            let target = operands[1];
+            let target = if target.mode == Mode::Immediate && !never {
+                Some(target.value)
+            } else {
+                None
+            };
+            (if always { None } else { Some(next) }, target)
+        }
+        _ => (Some(next), None),
+    }
+}
+
+/// A constant computed from two immediate operands, such as the return
+/// address `mul #1, #37, [rb+0]` stores before a `jz #0, #578` call.
+pub(crate) fn constant(decoded: &Decoded) -> Option<i64> {
+    match decoded {
+        Decoded::Instruction { code, operands, .. }
+            if (*code == 1 || *code == 2)
+                && operands[0].mode == Mode::Immediate
+                && operands[1].mode == Mode::Immediate =>
+        {
+            let (a, b) = (operands[0].value, operands[1].value);
+            Some(if *code == 1 { a.wrapping_add(b) } else { a.wrapping_mul(b) })
+        }
+        _ => None,
+    }
+}
+
+/// Disassembles `program` into one line per reachable instruction and one
+/// line per remaining data word.
+///
+/// Words right after an unconditional jump are only treated as code when the
+/// program computes their address as a constant, which is how Intcode
+/// programs pass return addresses to subroutines.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    let mut starts = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+    let mut constants = BTreeSet::new();
+    let mut after_jumps = BTreeSet::new();
+    let mut pending = vec![0];
+    while !pending.is_empty() {
+        while let Some(address) = pending.pop() {
+            if address >= program.len() || starts.contains(&address) {
+                continue;
+            }
+            let decoded = match decode(program, address) {
+                Some(decoded) => decoded,
+                None => continue,
+            };
+            starts.insert(address);
+            let (next, target) = successors(address, &decoded);
+            match next {
+                Some(next) => pending.push(next),
+                None => {
+                    after_jumps.insert(address + decoded_size(&decoded));
+                }
+            }
+            if let Some(target) = target {
+                if target >= 0 && (target as usize) < program.len() {
+                    targets.insert(target as usize);
+                    pending.push(target as usize);
+                }
+            }
+            if let Some(value) = constant(&decoded) {
+                if value >= 0 {
+                    constants.insert(value as usize);
+                }
+            }
+        }
+        pending = after_jumps
+            .intersection(&constants)
+            .filter(|address| !starts.contains(address))
+            .cloned()
+            .collect();
+        targets.extend(pending.iter().cloned());
+    }
+
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let jump_target = targets.contains(&address);
+        let decoded = if starts.contains(&address) {
+            decode(program, address).unwrap()
+        } else {
+            Decoded::Data
+        };
+        let size = decoded_size(&decoded);
+        lines.push(Line {
+            address,
+            words: program[address..address + size].to_vec(),
+            decoded,
+            jump_target,
+        });
+        address += size;
+    }
+    lines
+}
+
+/// The full listing as text, with a `lN:` label before every jump target.
+pub fn listing(program: &[i64]) -> String {
+    let mut text = String::new();
+    for line in disassemble(program) {
+        if line.jump_target {
+            text.push_str(&format!("l{}:\n", line.address));
+        }
+        text.push_str(&line.to_string());
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn test_decode() {
+        let program = vec![21201, 3, -1, -2, 1101, 1, 2, 3, 11101, 1, 2, 3, 307, 1];
+        let decoded = decode(&program, 0).unwrap();
+        assert_eq!(
+            decoded,
+            Decoded::Instruction {
+                code: 1,
+                mnemonic: "add",
+                operands: vec![
+                    Operand { mode: Mode::Relative, value: 3 },
+                    Operand { mode: Mode::Immediate, value: -1 },
+                    Operand { mode: Mode::Relative, value: -2 },
+                ],
+            }
+        );
+        assert!(decode(&program, 4).is_some());
+        // writes to an immediate operand
+        assert_eq!(decode(&program, 8), None);
+        // a mode digit of 3 and an instruction running past the end
+        assert_eq!(decode(&program, 12), None);
+        assert_eq!(decode(&[42], 0), None);
+        assert_eq!(decode(&[-1], 0), None);
+    }
+
+    #[test]
+    fn test_code_and_data() {
+        let program = assemble(
+            "in [x]
+             jz [x], #done
+             out [x]
+             done: hlt
+             x: data 1002",
+        )
+        .unwrap();
+        let lines = disassemble(&program);
+        let text: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        assert_eq!(text[0], "     0  3,8                         in [8]");
+        assert_eq!(text[1], "     2  1006,8,7                    jz [8], #7");
+        assert!(text[3].ends_with("hlt"));
+        assert!(lines[3].jump_target);
+        // 1002 would decode as an instruction, but it is never reached.
+        assert_eq!(lines[4].decoded, Decoded::Data);
+        assert_eq!(text[4], "     8  1002                        data 1002");
+    }
+
+    #[test]
+    fn test_follows_unconditional_jumps() {
+        // jz #0, #5 skips the words at 3 and 4 and never falls through.
+        let program = vec![1106, 0, 5, 1, 1, 204, -1, 99];
+        let lines = disassemble(&program);
+        assert_eq!(lines.iter().map(|l| l.address).collect::<Vec<_>>(), vec![0, 3, 4, 5, 7]);
+        assert_eq!(lines[1].decoded, Decoded::Data);
+        assert_eq!(lines[2].decoded, Decoded::Data);
+        assert!(lines[3].to_string().ends_with("out [rb-1]"));
+        assert!(listing(&program).contains("l5:\n"));
+    }
+
+    #[test]
+    fn test_follows_return_addresses() {
+        let program = assemble(
+            "       mul #1, #back, [rb+0]
+                    jz #0, #sub
+             back:  hlt
+             sub:   out #7
+                    jz #0, [rb+0]
+             skip:  data 5",
+        )
+        .unwrap();
+        let lines = disassemble(&program);
+        assert!(lines[2].to_string().ends_with("hlt"));
+        assert!(lines[2].jump_target);
+        assert!(lines[4].to_string().ends_with("jz #0, [rb+0]"));
+        assert_eq!(lines[5].decoded, Decoded::Data);
+    }
+
+    #[test]
+    fn test_listing_reassembles() {
+        let program = assemble(
+            "loop: in [rb+1]
+             eq [rb+1], #0, [flag]
+             jnz [flag], #end
+             out [rb+1]
+             jz #0, #loop
+             end: hlt
+             flag: data 0",
+        )
+        .unwrap();
+        let source: String = disassemble(&program)
+            .iter()
+            .map(|line| match &line.decoded {
+                Decoded::Instruction { .. } => line.to_string()[36..].to_string() + "\n",
+                Decoded::Data => format!("data {}\n", line.words[0]),
+            })
+            .collect();
+        assert_eq!(assemble(&source).unwrap(), program);
+    }
+}
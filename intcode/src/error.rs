@@ -0,0 +1,65 @@
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownOpcode(i64),
+    /// A parameter mode digit other than 0, 1 or 2.
+    InvalidMode(i64),
+    /// An instruction tried to write through an immediate-mode parameter.
+    ImmediateWrite,
+    NegativeAddress(i64),
+    /// An instruction tried to write at or past
+    /// [`MEMORY_LIMIT`](crate::MEMORY_LIMIT).
+    AddressTooLarge(i64),
+    /// An input instruction ran with no input left.
+    NoInput,
+    /// The instruction pointer left the memory the program occupies.
+    EndOfMemory,
+    /// The step budget set with `Machine::set_budget` ran out.
+    BudgetExhausted,
+    /// The machine is back in an earlier state, so it will never halt.
+    Repeats,
+    /// [`Machine::run_with`](crate::Machine::run_with) stopped because its
+    /// output closed.
+    OutputClosed,
+    /// A result did not fit in the word with overflow checks on, or a word
+    /// used as an address or opcode did not fit in an `i64`.
+    Overflow,
+}
+
+/// What went wrong, with the instruction pointer and the raw instruction
+/// word of the instruction that failed (0 if the word does not fit in an
+/// `i64`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub index: i64,
+    pub instruction: i64,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownOpcode(code) => write!(f, "unknown opcode {}", code),
+            ErrorKind::InvalidMode(mode) => write!(f, "invalid parameter mode {}", mode),
+            ErrorKind::ImmediateWrite => write!(f, "write to an immediate-mode parameter"),
+            ErrorKind::NegativeAddress(address) => write!(f, "negative address {}", address),
+            ErrorKind::AddressTooLarge(address) => write!(f, "address {} is past the memory limit", address),
+            ErrorKind::NoInput => write!(f, "no input left"),
+            ErrorKind::EndOfMemory => write!(f, "ran past the end of memory"),
+            ErrorKind::BudgetExhausted => write!(f, "step budget exhausted"),
+            ErrorKind::Repeats => write!(f, "state repeats, the program will never halt"),
+            ErrorKind::OutputClosed => write!(f, "output closed"),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {} (instruction {})", self.kind, self.index, self.instruction)
+    }
+}
+
+impl error::Error for Error {}
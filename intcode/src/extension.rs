@@ -0,0 +1,169 @@
+//! Opcodes added to a [`Machine`](crate::Machine) at run time, for trying
+//! out Intcode variants without changing the interpreter.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::ErrorKind;
+use crate::machine::{Machine, Mode};
+
+/// An operand of a custom instruction, resolved the way the standard
+/// instructions resolve theirs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param<W = i64> {
+    pub mode: Mode,
+    /// The operand's value: the word itself in immediate mode, otherwise
+    /// the word at `address`.
+    pub value: W,
+    /// The address the operand refers to, `None` in immediate mode.
+    pub address: Option<i64>,
+}
+
+/// Where the machine goes after a custom instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// On to the instruction after this one.
+    Continue,
+    Jump(i64),
+    Halt,
+}
+
+pub(crate) type Handler<W> = dyn Fn(&mut Machine<W>, &[Param<W>]) -> Result<Action, ErrorKind> + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct Extension<W> {
+    pub(crate) operands: usize,
+    pub(crate) handler: Arc<Handler<W>>,
+}
+
+impl<W> fmt::Debug for Extension<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Extension {{ operands: {} }}", self.operands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::machine::{run_program, State};
+
+    #[test]
+    fn test_debug_print() {
+        // 50: print the operand as output, tagged with its address.
+        let mut machine = Machine::new(&[50, 5, 1150, 7, 99, 42]);
+        machine.register_opcode(50, 1, |machine, params| {
+            machine.push_output(params[0].address.unwrap_or(-1));
+            machine.push_output(params[0].value);
+            Ok(Action::Continue)
+        });
+        machine.run().unwrap();
+        assert_eq!(machine.take_output(), vec![5, 42, -1, 7]);
+    }
+
+    #[test]
+    fn test_halt_with_code() {
+        // 98: output the operand and halt.
+        let mut machine = Machine::new(&[3, 9, 1008, 9, 0, 10, 98, 10, 99, 0, 0]);
+        machine.register_opcode(98, 1, |machine, params| {
+            machine.push_output(params[0].value);
+            Ok(Action::Halt)
+        });
+        machine.push_input(0);
+        assert_eq!(machine.resume(), Ok(State::Output(1)));
+        assert_eq!(machine.resume(), Ok(State::Halted));
+        assert_eq!(machine.index(), 6);
+    }
+
+    // Copies 5, 6, 7 from 9 to 12 and outputs the first two copies.
+    const COPY: [i64; 12] = [11120, 9, 12, 3, 4, 12, 4, 13, 99, 5, 6, 7];
+
+    fn memory_copy() -> Machine {
+        // 20: copy `length` words from `source` to `target`.
+        let mut machine = Machine::new(&COPY);
+        machine.register_opcode(20, 3, |machine, params| {
+            let (source, target) = (params[0].value, params[1].value);
+            for offset in 0..params[2].value {
+                let word = machine.read(source + offset);
+                machine.program_write(target + offset, word)?;
+            }
+            Ok(Action::Continue)
+        });
+        machine
+    }
+
+    #[test]
+    fn test_memory_copy() {
+        let mut machine = memory_copy();
+        machine.run().unwrap();
+        assert_eq!(machine.take_output(), vec![5, 6]);
+        assert_eq!(&machine.memory()[12..], &[5, 6, 7]);
+    }
+
+    #[test]
+    fn test_handler_writes_are_recorded() {
+        let mut machine = memory_copy();
+        machine.enable_history(10);
+        machine.enable_tracing();
+        machine.enable_profiling();
+        machine.watch_writes(13..14);
+        machine.step().unwrap();
+        assert_eq!(machine.history()[0].writes, vec![(12, 0), (13, 0), (14, 0)]);
+        assert_eq!(machine.take_trace()[0].writes, vec![(12, 0, 5), (13, 0, 6), (14, 0, 7)]);
+        assert_eq!(machine.profile().unwrap().writes(14), 1);
+        let hits = machine.take_write_hits();
+        assert_eq!((hits.len(), hits[0].address, hits[0].new), (1, 13, 6));
+        machine.run().unwrap();
+        assert_eq!(machine.rewind(10), 4);
+        assert_eq!(machine.memory(), COPY.to_vec());
+        assert_eq!(machine.index(), 0);
+    }
+
+    #[test]
+    fn test_failed_handler_undoes_writes() {
+        // 21: write the operand to 3 and 20 and output it, then fail.
+        let mut machine = Machine::new(&[1121, 7, 99, 0]);
+        machine.register_opcode(21, 1, |machine, params| {
+            machine.program_write(3, params[0].value)?;
+            machine.program_write(20, params[0].value)?;
+            machine.push_output(params[0].value);
+            Err(ErrorKind::Overflow)
+        });
+        assert_eq!(machine.step().unwrap_err().kind, ErrorKind::Overflow);
+        assert_eq!(machine.memory(), vec![1121, 7, 99, 0]);
+        assert!(machine.take_output().is_empty());
+        assert_eq!(machine.index(), 0);
+    }
+
+    #[test]
+    fn test_jumps_and_errors() {
+        // 60: jump to the operand; 61: fail if the operand is negative.
+        let mut machine = Machine::new(&[1160, 5, 104, 1, 99, 1161, -4, 104, 2, 99]);
+        machine.register_opcode(60, 1, |_, params| Ok(Action::Jump(params[0].value)));
+        machine.register_opcode(61, 1, |_, params| match params[0].value {
+            value if value < 0 => Err(ErrorKind::NegativeAddress(value)),
+            _ => Ok(Action::Continue),
+        });
+        assert_eq!(
+            machine.run(),
+            Err(Error { kind: ErrorKind::NegativeAddress(-4), index: 5, instruction: 1161 })
+        );
+        machine.write(6, 0).unwrap();
+        machine.run().unwrap();
+        assert_eq!(machine.take_output(), vec![2]);
+    }
+
+    #[test]
+    fn test_unregistered_opcode() {
+        assert_eq!(run_program(&[50, 0, 99], &[]).unwrap_err().kind, ErrorKind::UnknownOpcode(50));
+        let mut machine = Machine::new(&[50, 0, 51, 99]);
+        machine.register_opcode(50, 1, |_, _| Ok(Action::Continue));
+        assert_eq!(machine.run().unwrap_err(), Error { kind: ErrorKind::UnknownOpcode(51), index: 2, instruction: 51 });
+    }
+
+    #[test]
+    #[should_panic(expected = "opcode 2 is a standard opcode")]
+    fn test_standard_opcodes_are_reserved() {
+        Machine::new(&[99]).register_opcode(2, 3, |_, _| Ok(Action::Continue));
+    }
+}
@@ -0,0 +1,243 @@
+//! Control-flow graph of an Intcode program, found without running it.
+//!
+//! Blocks are built from the code [`disassemble`] finds: a block starts at
+//! address 0, at every jump target and after every jump or halt. Jumps with
+//! an immediate target get an edge to it; jumps through memory, such as a
+//! subroutine's `jz #0, [rb+0]` return, are marked unresolved instead.
+
+use std::collections::BTreeMap;
+
+use crate::disasm::{constant, disassemble, successors, Decoded, Operand};
+use crate::machine::Mode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction.
+    FallThrough,
+    /// A jump to an immediate target is taken.
+    Jump,
+    /// Where a subroutine called at the end of the block comes back to: the
+    /// block computes the address right after its closing jump as a
+    /// constant, the way `mul #1, #37, [rb+0]` / `jz #0, #578` does.
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    /// Address just past the last instruction.
+    pub end: usize,
+    pub instructions: Vec<(usize, Decoded)>,
+    pub edges: Vec<(EdgeKind, usize)>,
+    /// Ends in a jump whose target is only known at run time.
+    pub unresolved: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Graph {
+    pub blocks: Vec<Block>,
+}
+
+fn operands(decoded: &Decoded) -> &[Operand] {
+    match decoded {
+        Decoded::Instruction { operands, .. } => operands,
+        Decoded::Data => &[],
+    }
+}
+
+fn opcode(decoded: &Decoded) -> i64 {
+    match decoded {
+        Decoded::Instruction { code, .. } => *code,
+        Decoded::Data => 0,
+    }
+}
+
+/// Builds the control-flow graph of `program`.
+pub fn control_flow(program: &[i64]) -> Graph {
+    let lines = disassemble(program);
+    let code: BTreeMap<usize, Decoded> = lines
+        .iter()
+        .filter(|line| line.decoded != Decoded::Data)
+        .map(|line| (line.address, line.decoded.clone()))
+        .collect();
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in lines.iter().filter(|line| line.decoded != Decoded::Data) {
+        let continues = match blocks.last() {
+            Some(block) => {
+                let (_, last) = block.instructions.last().unwrap();
+                block.end == line.address && !line.jump_target && ![5, 6, 99].contains(&opcode(last))
+            }
+            None => false,
+        };
+        if !continues {
+            blocks.push(Block {
+                start: line.address,
+                end: line.address,
+                instructions: Vec::new(),
+                edges: Vec::new(),
+                unresolved: false,
+            });
+        }
+        let block = blocks.last_mut().unwrap();
+        block.instructions.push((line.address, line.decoded.clone()));
+        block.end = line.address + line.words.len();
+    }
+
+    for block in blocks.iter_mut() {
+        let (address, last) = block.instructions.last().cloned().unwrap();
+        match opcode(&last) {
+            99 => {}
+            5 | 6 => {
+                let (next, target) = successors(address, &last);
+                if let Some(next) = next.filter(|next| code.contains_key(next)) {
+                    block.edges.push((EdgeKind::FallThrough, next));
+                }
+                match target {
+                    Some(target) if target >= 0 && code.contains_key(&(target as usize)) => {
+                        block.edges.push((EdgeKind::Jump, target as usize));
+                    }
+                    Some(_) => {}
+                    None => {
+                        let condition = operands(&last)[0];
+                        let taken = condition.mode != Mode::Immediate || (condition.value != 0) == (opcode(&last) == 5);
+                        block.unresolved = taken;
+                    }
+                }
+                let returns_here = block
+                    .instructions
+                    .iter()
+                    .any(|(_, decoded)| constant(decoded) == Some(block.end as i64));
+                if next.is_none() && returns_here && code.contains_key(&block.end) {
+                    block.edges.push((EdgeKind::Return, block.end));
+                }
+            }
+            _ => {
+                if code.contains_key(&block.end) {
+                    block.edges.push((EdgeKind::FallThrough, block.end));
+                }
+            }
+        }
+    }
+    Graph { blocks }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Graph {
+    /// The block containing the instruction at `address`.
+    pub fn block_at(&self, address: usize) -> Option<&Block> {
+        self.blocks
+            .iter()
+            .find(|block| block.instructions.iter().any(|(a, _)| *a == address))
+    }
+
+    /// The graph in Graphviz DOT format, one box per block listing its
+    /// instructions. Jumps are labelled, returns dotted, and unresolved
+    /// jumps point at a shared `indirect jump` node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph intcode {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in &self.blocks {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|(address, decoded)| format!("{}: {}\\l", address, escape(&decoded.to_string())))
+                .collect();
+            dot.push_str(&format!("    b{} [label=\"{}\"];\n", block.start, label));
+        }
+        for block in &self.blocks {
+            for (kind, target) in &block.edges {
+                let attributes = match kind {
+                    EdgeKind::FallThrough => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Return => " [style=dotted, label=\"return\"]",
+                };
+                dot.push_str(&format!("    b{} -> b{}{};\n", block.start, target, attributes));
+            }
+            if block.unresolved {
+                dot.push_str(&format!("    b{} -> unresolved [style=dashed];\n", block.start));
+            }
+        }
+        if self.blocks.iter().any(|block| block.unresolved) {
+            dot.push_str("    unresolved [shape=plaintext, label=\"indirect jump\"];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn edges(graph: &Graph) -> Vec<(usize, EdgeKind, usize)> {
+        graph
+            .blocks
+            .iter()
+            .flat_map(|block| block.edges.iter().map(move |(kind, target)| (block.start, *kind, *target)))
+            .collect()
+    }
+
+    #[test]
+    fn test_loop() {
+        let program = assemble(
+            "loop: in [x]
+                   jz [x], #done
+                   out [x]
+                   jnz #1, #loop
+             done: hlt
+             x:    data 0",
+        )
+        .unwrap();
+        let graph = control_flow(&program);
+        let starts: Vec<usize> = graph.blocks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 5, 10]);
+        assert_eq!(graph.blocks[0].end, 5);
+        assert_eq!(graph.block_at(7).unwrap().start, 5);
+        assert_eq!(
+            edges(&graph),
+            vec![
+                (0, EdgeKind::FallThrough, 5),
+                (0, EdgeKind::Jump, 10),
+                (5, EdgeKind::Jump, 0),
+            ]
+        );
+        assert!(graph.blocks.iter().all(|b| !b.unresolved));
+    }
+
+    #[test]
+    fn test_subroutine_call() {
+        let program = assemble(
+            "       mul #1, #back, [rb+0]
+                    jz #0, #sub
+             back:  hlt
+             sub:   out #7
+                    jz #0, [rb+0]",
+        )
+        .unwrap();
+        let graph = control_flow(&program);
+        assert_eq!(edges(&graph), vec![(0, EdgeKind::Jump, 8), (0, EdgeKind::Return, 7)]);
+        assert!(graph.block_at(8).unwrap().unresolved);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph intcode {\n"));
+        assert!(dot.contains("    b0 [label=\"0: mul #1, #7, [rb+0]\\l4: jz #0, #8\\l\"];\n"));
+        assert!(dot.contains("    b0 -> b8 [label=\"jump\"];\n"));
+        assert!(dot.contains("    b0 -> b7 [style=dotted, label=\"return\"];\n"));
+        assert!(dot.contains("    b8 -> unresolved [style=dashed];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_never_taken_jump() {
+        // jnz #0, [0] never jumps, so its target read from memory does not matter.
+        let graph = control_flow(&[105, 0, 0, 99]);
+        assert_eq!(edges(&graph), vec![(0, EdgeKind::FallThrough, 3)]);
+        assert!(!graph.blocks[0].unresolved);
+        let graph = control_flow(&[1105, 0, 0, 99]);
+        assert_eq!(edges(&graph), vec![(0, EdgeKind::FallThrough, 3)]);
+    }
+}
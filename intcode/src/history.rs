@@ -0,0 +1,134 @@
+//! Undo log for stepping a [`Machine`](crate::Machine) backwards.
+
+use std::collections::VecDeque;
+
+/// What executing one instruction changed, enough to undo it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<W = i64> {
+    /// Instruction pointer before the instruction, which is where it ran.
+    pub index: i64,
+    pub relative_base: i64,
+    /// Addresses written, in order, and the values they held before.
+    pub writes: Vec<(i64, W)>,
+    /// Memory size before the instruction, in case a write grew it.
+    pub memory_len: usize,
+    pub input: Option<W>,
+    pub output: Option<W>,
+}
+
+/// The most recent changes, oldest first, dropping the oldest past `limit`.
+#[derive(Debug, Clone)]
+pub(crate) struct History<W> {
+    changes: VecDeque<Change<W>>,
+    limit: usize,
+}
+
+impl<W> History<W> {
+    pub(crate) fn new(limit: usize) -> History<W> {
+        History { changes: VecDeque::new(), limit }
+    }
+
+    pub(crate) fn push(&mut self, change: Change<W>) {
+        if self.changes.len() == self.limit {
+            self.changes.pop_front();
+        }
+        if self.limit > 0 {
+            self.changes.push_back(change);
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Change<W>> {
+        self.changes.pop_back()
+    }
+
+    pub(crate) fn changes(&self) -> &VecDeque<Change<W>> {
+        &self.changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::assemble;
+    use crate::machine::{Machine, State};
+
+    fn counter() -> Machine {
+        let program = assemble(
+            "loop: in [x]
+                   add [x], [total], [total]
+                   out [total]
+                   jnz #1, #loop
+             x:     data 0
+             total: data 0",
+        )
+        .unwrap();
+        let mut machine = Machine::new(&program);
+        machine.enable_history(100);
+        machine
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut machine = counter();
+        let start = machine.snapshot();
+        machine.push_input(5);
+        assert_eq!(machine.resume(), Ok(State::Output(5)));
+        machine.push_input(7);
+        assert_eq!(machine.resume(), Ok(State::Output(12)));
+        let after = machine.snapshot();
+        assert_eq!(machine.history().len(), 7);
+        assert_eq!(machine.history()[1].writes, vec![(12, 0)]);
+
+        assert_eq!(machine.rewind(2), 2);
+        assert_eq!(machine.index(), 2);
+        assert_eq!(machine.read(11), 7);
+        assert_eq!(machine.read(12), 5);
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.take_output(), vec![12]);
+        assert_eq!(machine.snapshot(), after);
+
+        assert_eq!(machine.rewind(100), 7);
+        assert_eq!(machine.snapshot().memory, start.memory);
+        assert_eq!(machine.pending_input(), 2);
+        assert!(!machine.step_back());
+    }
+
+    #[test]
+    fn test_rewind_to_write() {
+        let mut machine = counter();
+        for value in [4, 1, 3].iter() {
+            machine.push_input(*value);
+            machine.resume().unwrap();
+        }
+        assert_eq!(machine.rewind_to_write(12), Some(2));
+        // About to add the last input to the total again.
+        assert_eq!(machine.index(), 2);
+        assert_eq!(machine.read(12), 5);
+        assert_eq!(machine.rewind_to_write(11), Some(1));
+        assert_eq!(machine.index(), 0);
+        assert_eq!(machine.rewind_to_write(99), None);
+        assert_eq!(machine.index(), 0);
+    }
+
+    #[test]
+    fn test_undo_growth_and_halt() {
+        let mut machine = Machine::new(&[1101, 2, 3, 10, 99]);
+        machine.enable_history(10);
+        machine.run().unwrap();
+        assert!(machine.is_halted());
+        assert!(machine.step_back());
+        assert!(!machine.is_halted());
+        assert!(machine.step_back());
+        assert_eq!(machine.memory(), vec![1101, 2, 3, 10, 99]);
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut machine = counter();
+        machine.enable_history(2);
+        machine.push_input(1);
+        machine.resume().unwrap();
+        assert_eq!(machine.rewind(10), 2);
+        assert_eq!(machine.index(), 2);
+    }
+}
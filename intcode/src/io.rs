@@ -0,0 +1,257 @@
+//! Where a [`Machine`](crate::Machine) gets its input and sends its output
+//! when run with [`Machine::run_with`](crate::Machine::run_with).
+//!
+//! Queues, closures, `mpsc` channels and line-based readers and writers are
+//! supported out of the box, so each day can wire its machines up the way
+//! the puzzle needs.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, Sender};
+
+pub trait Input<W = i64> {
+    /// The next input value, or `None` if there is none to give right now.
+    fn read(&mut self) -> Option<W>;
+}
+
+pub trait Output<W = i64> {
+    fn write(&mut self, value: W);
+
+    /// Whether the output takes no more values, which stops
+    /// [`Machine::run_with`](crate::Machine::run_with).
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+impl<W, T: Input<W> + ?Sized> Input<W> for &mut T {
+    fn read(&mut self) -> Option<W> {
+        (**self).read()
+    }
+}
+
+impl<W, T: Output<W> + ?Sized> Output<W> for &mut T {
+    fn write(&mut self, value: W) {
+        (**self).write(value)
+    }
+
+    fn is_closed(&self) -> bool {
+        (**self).is_closed()
+    }
+}
+
+impl<W> Input<W> for VecDeque<W> {
+    fn read(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+
+impl<W> Output<W> for VecDeque<W> {
+    fn write(&mut self, value: W) {
+        self.push_back(value);
+    }
+}
+
+impl<W> Output<W> for Vec<W> {
+    fn write(&mut self, value: W) {
+        self.push(value);
+    }
+}
+
+/// Blocks until a value arrives; gives `None` once every sender is gone.
+impl<W> Input<W> for Receiver<W> {
+    fn read(&mut self) -> Option<W> {
+        self.recv().ok()
+    }
+}
+
+/// Values sent after the receiver is gone are dropped.
+impl<W> Output<W> for Sender<W> {
+    fn write(&mut self, value: W) {
+        let _ = self.send(value);
+    }
+}
+
+/// Input from a `FnMut() -> Option<W>` or output to a `FnMut(W)`.
+pub struct Closure<F>(pub F);
+
+impl<W, F: FnMut() -> Option<W>> Input<W> for Closure<F> {
+    fn read(&mut self) -> Option<W> {
+        (self.0)()
+    }
+}
+
+impl<W, F: FnMut(W)> Output<W> for Closure<F> {
+    fn write(&mut self, value: W) {
+        (self.0)(value)
+    }
+}
+
+/// Reads one number per line; gives `None` at the end of the input. Blank
+/// lines are skipped, and so are lines that are not a number, which are
+/// kept for [`take_skipped`](LineInput::take_skipped).
+pub struct LineInput<R> {
+    reader: R,
+    skipped: Vec<String>,
+}
+
+impl<R: BufRead> LineInput<R> {
+    pub fn new(reader: R) -> LineInput<R> {
+        LineInput { reader, skipped: Vec::new() }
+    }
+
+    /// Removes and returns the lines skipped for not being a number, trimmed.
+    pub fn take_skipped(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.skipped)
+    }
+}
+
+impl LineInput<io::StdinLock<'static>> {
+    pub fn stdin() -> Self {
+        LineInput::new(io::stdin().lock())
+    }
+}
+
+impl<W: FromStr, R: BufRead> Input<W> for LineInput<R> {
+    fn read(&mut self) -> Option<W> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            }
+            let text = line.trim();
+            if text.is_empty() {
+                continue;
+            }
+            match text.parse() {
+                Ok(value) => return Some(value),
+                Err(_) => self.skipped.push(text.to_string()),
+            }
+        }
+    }
+}
+
+/// Writes one number per line, flushing after each so interactive programs
+/// show their output before asking for more input. The first failed write,
+/// such as to a pipe whose reader has gone, closes the output.
+pub struct LineOutput<T> {
+    writer: T,
+    error: Option<io::Error>,
+}
+
+impl<T: Write> LineOutput<T> {
+    pub fn new(writer: T) -> LineOutput<T> {
+        LineOutput { writer, error: None }
+    }
+
+    /// The error that closed the output, if any.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.writer
+    }
+}
+
+impl LineOutput<io::Stdout> {
+    pub fn stdout() -> Self {
+        LineOutput::new(io::stdout())
+    }
+}
+
+impl<W: Display, T: Write> Output<W> for LineOutput<T> {
+    fn write(&mut self, value: W) {
+        if self.error.is_none() {
+            self.error = writeln!(self.writer, "{}", value).and_then(|_| self.writer.flush()).err();
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Error, ErrorKind};
+    use crate::machine::{Machine, State};
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    // Doubles every input until it reads 0.
+    const DOUBLER: [i64; 15] = [3, 100, 1006, 100, 14, 1002, 100, 2, 101, 4, 101, 1105, 1, 0, 99];
+
+    #[test]
+    fn test_queues() {
+        let mut machine = Machine::new(&DOUBLER);
+        let mut input: VecDeque<i64> = vec![1, 2, 3].into();
+        let mut output = Vec::new();
+        assert_eq!(machine.run_with(&mut input, &mut output), Ok(State::NeedsInput));
+        assert_eq!(output, vec![2, 4, 6]);
+        input.push_back(0);
+        assert_eq!(machine.run_with(&mut input, &mut output), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut next = 5;
+        let mut sum = 0;
+        let input = Closure(|| {
+            next -= 1;
+            Some(next)
+        });
+        let output = Closure(|value| sum += value);
+        assert_eq!(Machine::new(&DOUBLER).run_with(input, output), Ok(State::Halted));
+        assert_eq!(sum, 2 * (4 + 3 + 2 + 1));
+    }
+
+    #[test]
+    fn test_channels() {
+        let (to_machine, input) = channel();
+        let (output, from_machine) = channel();
+        let handle = thread::spawn(move || Machine::new(&DOUBLER).run_with(input, output));
+        for value in 1..4 {
+            to_machine.send(value).unwrap();
+            assert_eq!(from_machine.recv(), Ok(value * 2));
+        }
+        drop(to_machine);
+        assert_eq!(handle.join().unwrap(), Ok(State::NeedsInput));
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut input = LineInput::new("7\n\n x \n-3\n1.5\n".as_bytes());
+        let mut output = LineOutput::new(Vec::new());
+        assert_eq!(Machine::new(&DOUBLER).run_with(&mut input, &mut output), Ok(State::NeedsInput));
+        assert_eq!(String::from_utf8(output.into_inner()).unwrap(), "14\n-6\n");
+        assert_eq!(input.take_skipped(), vec!["x", "1.5"]);
+        assert!(input.take_skipped().is_empty());
+    }
+
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_closed_output() {
+        let mut machine = Machine::new(&DOUBLER);
+        let mut output = LineOutput::new(ClosedPipe);
+        let result = machine.run_with(Closure(|| Some(1)), &mut output);
+        assert_eq!(result, Err(Error { kind: ErrorKind::OutputClosed, index: 11, instruction: 1105 }));
+        assert_eq!(output.error().map(io::Error::kind), Some(io::ErrorKind::BrokenPipe));
+    }
+}
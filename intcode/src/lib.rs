@@ -0,0 +1,42 @@
+//! Shared Intcode computer used by the Advent of Code 2019 days.
+
+mod ascii;
+mod asm;
+pub mod conformance;
+mod debugger;
+mod disasm;
+mod error;
+mod extension;
+mod flow;
+mod history;
+mod io;
+mod machine;
+mod network;
+mod parse;
+mod profile;
+mod repeat;
+mod snapshot;
+mod trace;
+mod watch;
+mod word;
+
+pub use ascii::{Ascii, Transcript};
+pub use asm::{assemble, AsmError};
+pub use debugger::Debugger;
+pub use disasm::{decode, disassemble, listing, Decoded, Line, Operand};
+pub use error::{Error, ErrorKind};
+pub use extension::{Action, Param};
+pub use flow::{control_flow, Block, EdgeKind, Graph};
+pub use history::Change;
+pub use io::{Closure, Input, LineInput, LineOutput, Output};
+pub use machine::{parse_instruction, run_program, Instruction, Machine, Mode, State, MEMORY_LIMIT};
+pub use network::{Control, Network, NetworkError, Packet, Stop};
+pub use parse::{parse_program, parse_words, read_program, ParseError, ParseErrorKind};
+pub use profile::Profile;
+pub use snapshot::Snapshot;
+pub use trace::{first_divergence, Divergence, TraceOperand, TraceRecord};
+pub use watch::{Reason, WriteHit};
+pub use word::Word;
+
+#[cfg(feature = "bigint")]
+pub use num_bigint::BigInt;
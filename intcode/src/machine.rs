@@ -0,0 +1,1002 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::asm::{write_operand, MNEMONICS};
+use crate::error::{Error, ErrorKind};
+use crate::extension::{Action, Extension, Param};
+use crate::history::{Change, History};
+use crate::io::{Input, Output};
+use crate::profile::Profile;
+use crate::repeat::RepeatDetector;
+use crate::snapshot::Snapshot;
+use crate::trace::{TraceOperand, TraceRecord};
+use crate::watch::{instruction_size, Watch, WriteHit};
+use crate::word::Word;
+
+mod compile;
+
+use compile::Compiled;
+
+/// Number of words memory may grow to. Writes at or past it fail with
+/// [`ErrorKind::AddressTooLarge`] rather than allocate without bound.
+pub const MEMORY_LIMIT: usize = 1 << 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+fn int_to_mode(mode: i64) -> Result<Mode, ErrorKind> {
+    match mode {
+        0 => Ok(Mode::Position),
+        1 => Ok(Mode::Immediate),
+        2 => Ok(Mode::Relative),
+        _ => Err(ErrorKind::InvalidMode(mode)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: i64,
+    /// Modes of the first, second and third parameter.
+    pub modes: [Mode; 3],
+}
+
+pub fn parse_instruction(instruction: i64) -> Result<Instruction, ErrorKind> {
+    let opcode = instruction % 100;
+    let mode1 = instruction % 1000 / 100;
+    let mode2 = instruction % 10000 / 1000;
+    let mode3 = instruction / 10000;
+    let modes = [int_to_mode(mode1)?, int_to_mode(mode2)?, int_to_mode(mode3)?];
+    Ok(Instruction { code: opcode, modes })
+}
+
+/// Why [`Machine::resume`] handed control back to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State<W = i64> {
+    /// The next instruction reads input and the input queue is empty.
+    NeedsInput,
+    /// The program produced a value.
+    Output(W),
+    Halted,
+}
+
+/// An Intcode computer: memory, instruction pointer, relative base and
+/// the queued input and produced output.
+///
+/// Memory is a flat vector that grows with zeros when the program writes
+/// past its end, up to [`MEMORY_LIMIT`] words; reads past the end return 0
+/// without growing it.
+///
+/// Memory holds `i64` words unless another [`Word`] type is chosen, for
+/// example `Machine<BigInt>` with the `bigint` feature.
+#[derive(Debug, Clone)]
+pub struct Machine<W = i64> {
+    memory: Vec<W>,
+    /// Decoded instructions by address, when caching is enabled.
+    cache: Option<Vec<Option<Instruction>>>,
+    index: i64,
+    relative_base: i64,
+    input: VecDeque<W>,
+    output: Vec<W>,
+    halted: bool,
+    /// Whether arithmetic fails with [`ErrorKind::Overflow`] instead of wrapping.
+    checked: bool,
+    /// Undo log, when recording is enabled.
+    history: Option<History<W>>,
+    /// Addresses and previous values of the writes by the instruction that
+    /// ran last, in order.
+    writes: Vec<(i64, W)>,
+    profile: Option<Box<Profile>>,
+    /// Instructions executed so far.
+    steps: u64,
+    /// Step count at which execution stops, if limited.
+    budget: Option<u64>,
+    repeats: Option<Box<RepeatDetector<W>>>,
+    watch: Option<Box<Watch<W>>>,
+    /// Custom opcodes by number.
+    extensions: BTreeMap<i64, Extension<W>>,
+    /// Trace records not taken yet, when tracing is enabled.
+    trace: Option<Vec<TraceRecord<W>>>,
+    /// Compiled blocks by start address, when compilation is enabled.
+    compiled: Option<Box<Compiled<W>>>,
+}
+
+/// `address` if an instruction may write there.
+fn writable(address: i64) -> Result<i64, ErrorKind> {
+    if address < 0 {
+        return Err(ErrorKind::NegativeAddress(address));
+    }
+    if address as u64 >= MEMORY_LIMIT as u64 {
+        return Err(ErrorKind::AddressTooLarge(address));
+    }
+    Ok(address)
+}
+
+impl Machine {
+    pub fn new(program: &[i64]) -> Machine {
+        Machine::from_program(program)
+    }
+}
+
+impl<W: Word> Machine<W> {
+    /// A machine with any word type, loaded with an `i64` program.
+    pub fn from_program(program: &[i64]) -> Self {
+        Machine::with_memory(program.iter().map(|word| W::from_i64(*word)).collect())
+    }
+
+    /// A machine loaded with a program whose words may not fit in an `i64`.
+    pub fn from_words(program: &[W]) -> Self {
+        Machine::with_memory(program.to_vec())
+    }
+
+    fn with_memory(memory: Vec<W>) -> Self {
+        Machine {
+            memory,
+            cache: None,
+            index: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            halted: false,
+            checked: false,
+            history: None,
+            writes: Vec::new(),
+            profile: None,
+            steps: 0,
+            budget: None,
+            repeats: None,
+            watch: None,
+            extensions: BTreeMap::new(),
+            trace: None,
+            compiled: None,
+        }
+    }
+
+    /// Caches decoded instructions so each address is only decoded once.
+    /// Writes to an address drop its cached instruction, so self-modifying
+    /// programs behave the same with and without the cache.
+    pub fn enable_cache(&mut self) {
+        if self.cache.is_none() {
+            self.cache = Some(Vec::new());
+        }
+    }
+
+    /// Compiles straight-line runs of instructions into closures the first
+    /// time they run, which [`run`](Machine::run) and
+    /// [`resume`](Machine::resume) then use instead of decoding every
+    /// instruction. Writes to compiled code drop it, so self-modifying
+    /// programs behave the same as without compilation. History, profiling,
+    /// watchpoints, tracing and repeat detection see every step and turn
+    /// the compiled code off while they are enabled.
+    pub fn enable_compilation(&mut self) {
+        if self.compiled.is_none() {
+            self.compiled = Some(Box::new(Compiled::new()));
+        }
+    }
+
+    /// Records an undo log of the last `limit` instructions, so the machine
+    /// can be stepped backwards. Starts a fresh log if one was recording.
+    pub fn enable_history(&mut self, limit: usize) {
+        self.history = Some(History::new(limit));
+    }
+
+    /// Stops recording and drops the undo log.
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Makes additions and multiplications whose result does not fit in
+    /// the word fail with [`ErrorKind::Overflow`], leaving the machine at
+    /// the faulting instruction, instead of wrapping around.
+    pub fn enable_overflow_checks(&mut self) {
+        self.checked = true;
+    }
+
+    /// Records every executed instruction, its operands and its effects.
+    /// Collect the records with [`take_trace`](Machine::take_trace).
+    pub fn enable_tracing(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Removes and returns the trace records since the last call.
+    pub fn take_trace(&mut self) -> Vec<TraceRecord<W>> {
+        self.trace.as_mut().map_or_else(Vec::new, std::mem::take)
+    }
+
+    /// Teaches the machine opcode `code`, an instruction with `operands`
+    /// operands (at most 3, with modes like the standard instructions).
+    /// `handler` gets the resolved operands and the machine, which stands at
+    /// the custom instruction, and says where to go next. Registering a
+    /// code again replaces its handler.
+    ///
+    /// Handlers write with [`program_write`](Machine::program_write), so
+    /// the writes count as the instruction's own. A handler that fails
+    /// leaves the machine as it was before the instruction.
+    ///
+    /// # Panics
+    ///
+    /// If `code` is a standard opcode or not in `0..100`, or `operands` is
+    /// more than 3.
+    pub fn register_opcode<F>(&mut self, code: i64, operands: usize, handler: F)
+    where
+        F: Fn(&mut Machine<W>, &[Param<W>]) -> Result<Action, ErrorKind> + Send + Sync + 'static,
+    {
+        assert!((0..100).contains(&code), "opcode {} is not in 0..100", code);
+        assert!(
+            MNEMONICS.iter().all(|(_, standard, _)| *standard != code),
+            "opcode {} is a standard opcode",
+            code
+        );
+        assert!(operands <= 3, "custom instructions take at most 3 operands, not {}", operands);
+        let handler = Arc::new(handler);
+        self.extensions.insert(code, Extension { operands, handler });
+    }
+
+    /// The recorded changes, oldest first.
+    pub fn history(&self) -> Vec<Change<W>> {
+        match &self.history {
+            Some(history) => history.changes().iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Undoes the last recorded instruction. Returns false if there is
+    /// nothing left to undo. Output the caller has already taken stays taken.
+    pub fn step_back(&mut self) -> bool {
+        let change = match self.history.as_mut().and_then(History::pop) {
+            Some(change) => change,
+            None => return false,
+        };
+        for (address, old) in change.writes.into_iter().rev() {
+            self.poke(address as usize, old);
+        }
+        if let Some(repeats) = self.repeats.as_mut() {
+            repeats.reset();
+        }
+        self.memory.truncate(change.memory_len);
+        if let Some(value) = change.input {
+            self.input.push_front(value);
+        }
+        if change.output.is_some() {
+            self.output.pop();
+        }
+        self.index = change.index;
+        self.relative_base = change.relative_base;
+        self.halted = false;
+        self.steps = self.steps.saturating_sub(1);
+        true
+    }
+
+    /// Undoes up to `steps` instructions and returns how many were undone.
+    pub fn rewind(&mut self, steps: usize) -> usize {
+        (0..steps).take_while(|_| self.step_back()).count()
+    }
+
+    /// Rewinds to just before the last recorded instruction that wrote to
+    /// `address` and returns how many instructions were undone, or `None`
+    /// (leaving the machine alone) if no recorded instruction wrote there.
+    pub fn rewind_to_write(&mut self, address: i64) -> Option<usize> {
+        let changes = self.history.as_ref()?.changes();
+        let steps = changes.iter().rev().position(|change| change.writes.iter().any(|(a, _)| *a == address))? + 1;
+        Some(self.rewind(steps))
+    }
+
+    /// Starts counting executions, reads and writes. Until this is called
+    /// the machine does no profiling work at all.
+    pub fn enable_profiling(&mut self) {
+        if self.profile.is_none() {
+            self.profile = Some(Box::default());
+        }
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    /// Allows at most `steps` more instructions before [`step`](Machine::step)
+    /// fails with [`ErrorKind::BudgetExhausted`]. `None` removes the limit.
+    pub fn set_budget(&mut self, steps: Option<u64>) {
+        self.budget = steps.map(|steps| self.steps.saturating_add(steps));
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Makes [`step`](Machine::step) fail with [`ErrorKind::Repeats`] when
+    /// the machine comes back to an earlier memory, instruction pointer and
+    /// relative base without any input in between, which proves the program
+    /// will never halt.
+    pub fn detect_repeats(&mut self) {
+        if self.repeats.is_none() {
+            self.repeats = Some(Box::new(RepeatDetector::new(&self.memory)));
+        }
+    }
+
+    /// Reports every write by the program to an address in `addresses`.
+    /// Collect the reports with [`take_write_hits`](Machine::take_write_hits).
+    pub fn watch_writes(&mut self, addresses: Range<i64>) {
+        self.watch.get_or_insert_with(Box::default).add(addresses);
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        if let Some(watch) = self.watch.as_mut() {
+            watch.clear();
+        }
+    }
+
+    /// The watched address ranges.
+    pub fn watchpoints(&self) -> Vec<Range<i64>> {
+        self.watch.as_ref().map_or_else(Vec::new, |watch| watch.ranges().to_vec())
+    }
+
+    /// Reports every write that lands on an instruction that has already
+    /// run, or on the instruction that is about to run.
+    pub fn detect_self_modification(&mut self) {
+        self.watch.get_or_insert_with(Box::default).detect_self_modification();
+    }
+
+    pub(crate) fn detects_self_modification(&self) -> bool {
+        self.watch.as_ref().is_some_and(|watch| watch.detects_self_modification())
+    }
+
+    /// Removes and returns the writes reported since the last call.
+    pub fn take_write_hits(&mut self) -> Vec<WriteHit<W>> {
+        self.watch.as_mut().map_or_else(Vec::new, |watch| watch.take_hits())
+    }
+
+    pub fn read(&self, address: i64) -> W {
+        if address < 0 {
+            return W::default();
+        }
+        self.memory.get(address as usize).cloned().unwrap_or_default()
+    }
+
+    /// Writes `value` to `address`, growing memory if needed. Fails for
+    /// negative addresses and addresses past [`MEMORY_LIMIT`]. The write is
+    /// not part of any instruction, so history, watchpoints, the profiler
+    /// and the trace do not see it.
+    pub fn write(&mut self, address: i64, value: W) -> Result<(), ErrorKind> {
+        let address = writable(address)?;
+        self.poke(address as usize, value);
+        if let Some(repeats) = self.repeats.as_mut() {
+            repeats.reset();
+        }
+        Ok(())
+    }
+
+    fn poke(&mut self, address: usize, value: W) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, W::default());
+        }
+        if let Some(repeats) = self.repeats.as_mut() {
+            repeats.update(address, &self.memory[address], &value);
+        }
+        self.memory[address] = value;
+        if let Some(slot) = self.cache.as_mut().and_then(|cache| cache.get_mut(address)) {
+            *slot = None;
+        }
+        if let Some(compiled) = self.compiled.as_mut() {
+            compiled.written(address);
+        }
+    }
+
+    /// The machine's full state, to save and restore later.
+    pub fn snapshot(&self) -> Snapshot<W> {
+        Snapshot {
+            memory: self.memory.clone(),
+            index: self.index,
+            relative_base: self.relative_base,
+            halted: self.halted,
+            steps: self.steps,
+            overflow_checks: self.checked,
+            budget: self.budget,
+            input: self.input.iter().cloned().collect(),
+            output: self.output.clone(),
+        }
+    }
+
+    /// A machine that continues exactly where `snapshot` was taken, with
+    /// the same step count and budget. Repeat detection, history, profiling,
+    /// watchpoints, tracing, caching, compilation and custom opcodes are not
+    /// part of a snapshot and start off.
+    pub fn from_snapshot(snapshot: Snapshot<W>) -> Self {
+        Machine {
+            index: snapshot.index,
+            relative_base: snapshot.relative_base,
+            input: snapshot.input.into(),
+            output: snapshot.output,
+            halted: snapshot.halted,
+            steps: snapshot.steps,
+            checked: snapshot.overflow_checks,
+            budget: snapshot.budget,
+            ..Machine::with_memory(snapshot.memory)
+        }
+    }
+
+    /// Memory from address 0 up to the highest address in use.
+    pub fn memory(&self) -> Vec<W> {
+        self.memory.clone()
+    }
+
+    pub fn index(&self) -> i64 {
+        self.index
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn push_input(&mut self, value: W) {
+        self.input.push_back(value);
+    }
+
+    /// Number of queued input values the program has not read yet.
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Adds `value` to the output as if the program had output it, for
+    /// custom instructions.
+    pub fn push_output(&mut self, value: W) {
+        self.output.push(value);
+    }
+
+    /// Writes `value` to `address` as if the program had written it, for
+    /// custom instructions: the write is recorded in the instruction's
+    /// history entry, trace record and profile, and watchpoints see it.
+    /// Fails like [`write`](Machine::write).
+    pub fn program_write(&mut self, address: i64, value: W) -> Result<(), ErrorKind> {
+        self.store(writable(address)?, value);
+        Ok(())
+    }
+
+    /// Removes and returns everything the program has output so far.
+    pub fn take_output(&mut self) -> Vec<W> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Writes on behalf of an instruction, remembering the old value.
+    fn store(&mut self, address: i64, value: W) {
+        self.writes.push((address, self.read(address)));
+        if address >= 0 {
+            self.poke(address as usize, value);
+        }
+    }
+
+    fn get_value(&self, idx: i64, mode: Mode) -> Result<W, ErrorKind> {
+        match mode {
+            Mode::Immediate => Ok(self.read(idx)),
+            _ => Ok(self.read(self.get_index(idx, mode)?)),
+        }
+    }
+
+    fn get_index(&self, idx: i64, mode: Mode) -> Result<i64, ErrorKind> {
+        let pnt = self.read(idx);
+        let address = match mode {
+            Mode::Position => pnt.to_i64(),
+            Mode::Immediate => return Err(ErrorKind::ImmediateWrite),
+            Mode::Relative => pnt.to_i64().and_then(|pnt| pnt.checked_add(self.relative_base)),
+        }
+        .ok_or(ErrorKind::Overflow)?;
+        if address < 0 {
+            return Err(ErrorKind::NegativeAddress(address));
+        }
+        Ok(address)
+    }
+
+    /// Number of words in the instruction starting with `word`, custom
+    /// instructions included.
+    fn instruction_size(&self, word: i64) -> usize {
+        match self.extensions.get(&(word % 100)) {
+            Some(extension) => 1 + extension.operands,
+            None => instruction_size(word),
+        }
+    }
+
+    /// Addresses the instruction at `index` reads operands from.
+    fn read_addresses(&self, index: i64, word: i64) -> Vec<i64> {
+        let count = match word % 100 {
+            1 | 2 | 5 | 6 | 7 | 8 => 2,
+            4 | 9 => 1,
+            code => self.extensions.get(&code).map_or(0, |extension| extension.operands),
+        };
+        let modes = match parse_instruction(word) {
+            Ok(instruction) => instruction.modes,
+            Err(_) => return Vec::new(),
+        };
+        (0..count)
+            .filter(|i| modes[*i] != Mode::Immediate)
+            .filter_map(|i| self.get_index(index + 1 + i as i64, modes[i]).ok())
+            .collect()
+    }
+
+    fn decode(&mut self, index: usize, word: i64) -> Result<Instruction, ErrorKind> {
+        let cache = match self.cache.as_mut() {
+            Some(cache) => cache,
+            None => return parse_instruction(word),
+        };
+        if let Some(Some(instruction)) = cache.get(index) {
+            return Ok(*instruction);
+        }
+        let instruction = parse_instruction(word)?;
+        if index >= cache.len() {
+            cache.resize(index + 1, None);
+        }
+        cache[index] = Some(instruction);
+        Ok(instruction)
+    }
+
+    /// Executes a single instruction. Does nothing once the program has halted.
+    /// On error the machine is left as it was before the instruction.
+    pub fn step(&mut self) -> Result<(), Error> {
+        if self.halted {
+            return Ok(());
+        }
+        let index = self.index;
+        let word = self.read(index).to_i64();
+        let instruction = word.unwrap_or(0);
+        let error = |kind| Error {
+            kind,
+            index,
+            instruction,
+        };
+        if self.budget.is_some_and(|budget| self.steps >= budget) {
+            return Err(error(ErrorKind::BudgetExhausted));
+        }
+        if let Some(repeats) = self.repeats.as_mut() {
+            if !self.input.is_empty() {
+                repeats.reset();
+            } else if repeats.repeats(index, self.relative_base, &self.memory) {
+                return Err(error(ErrorKind::Repeats));
+            }
+        }
+        if self.history.is_none() && self.profile.is_none() && self.watch.is_none() && self.trace.is_none() {
+            self.execute(index, word).map_err(error)?;
+            self.steps += 1;
+            return Ok(());
+        }
+
+        let relative_base = self.relative_base;
+        let memory_len = self.memory.len();
+        let (input, produced) = (self.input.front().cloned(), self.output.len());
+        let reads = match self.profile {
+            Some(_) => self.read_addresses(index, instruction),
+            None => Vec::new(),
+        };
+        let watched = self.watch.as_ref().map(|_| {
+            let words: Vec<W> = (index..index + self.instruction_size(instruction) as i64).map(|a| self.read(a)).collect();
+            let next = index + words.len() as i64;
+            let size = self.instruction_size(self.read(next).to_i64().unwrap_or(0));
+            (words, next..next + size as i64)
+        });
+        let operands = self.trace.as_ref().map(|_| self.trace_operands(index, instruction));
+        self.execute(index, word).map_err(error)?;
+        self.steps += 1;
+        let writes: Vec<(i64, W, W)> =
+            self.writes.iter().map(|(address, old)| (*address, old.clone(), self.read(*address))).collect();
+        let input = if instruction % 100 == 3 { input } else { None };
+        let output = if self.output.len() > produced { self.output.last().cloned() } else { None };
+        if let (Some(trace), Some(operands)) = (self.trace.as_mut(), operands) {
+            trace.push(TraceRecord {
+                step: self.steps - 1,
+                ip: index,
+                opcode: instruction % 100,
+                operands,
+                writes: writes.clone(),
+                relative_base,
+                input: input.clone(),
+                output: output.clone(),
+            });
+        }
+        if let (Some(watch), Some((words, upcoming))) = (self.watch.as_mut(), watched) {
+            watch.record(index, &words, upcoming, &writes);
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            let writes: Vec<i64> = writes.iter().map(|(address, _, _)| *address).collect();
+            profile.record(index, instruction % 100, &reads, &writes);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.push(Change {
+                index,
+                relative_base,
+                writes: std::mem::take(&mut self.writes),
+                memory_len,
+                input,
+                output,
+            });
+        }
+        Ok(())
+    }
+
+    /// The operands of the instruction `word` at `index` as a trace shows them.
+    fn trace_operands(&self, index: i64, word: i64) -> Vec<TraceOperand<W>> {
+        let code = word % 100;
+        let count = match MNEMONICS.iter().find(|(_, standard, _)| *standard == code) {
+            Some((_, _, count)) => *count,
+            None => self.extensions.get(&code).map_or(0, |extension| extension.operands),
+        };
+        let modes = match parse_instruction(word) {
+            Ok(instruction) => instruction.modes,
+            Err(_) => return Vec::new(),
+        };
+        (0..count)
+            .map(|i| {
+                let (at, mode) = (index + 1 + i as i64, modes[i]);
+                let address = match mode {
+                    Mode::Immediate => None,
+                    _ => self.get_index(at, mode).ok(),
+                };
+                let value = match address {
+                    _ if write_operand(code) == Some(i + 1) => None,
+                    Some(address) => Some(self.read(address)),
+                    None => Some(self.read(at)),
+                };
+                TraceOperand { mode, word: self.read(at), address, value }
+            })
+            .collect()
+    }
+
+    /// Runs the instruction `word` at `index`; `word` is `None` if it does
+    /// not fit in an `i64`.
+    fn execute(&mut self, index: i64, word: Option<i64>) -> Result<(), ErrorKind> {
+        if index < 0 {
+            return Err(ErrorKind::NegativeAddress(index));
+        }
+        if index as usize >= self.memory.len() {
+            return Err(ErrorKind::EndOfMemory);
+        }
+        let instruction = self.decode(index as usize, word.ok_or(ErrorKind::Overflow)?)?;
+        self.writes.clear();
+        let [mode1, mode2, mode3] = instruction.modes;
+        match instruction.code {
+            1 | 2 => {
+                let val1 = self.get_value(index + 1, mode1)?;
+                let val2 = self.get_value(index + 2, mode2)?;
+                let insert_index = writable(self.get_index(index + 3, mode3)?)?;
+                let new_val = match (instruction.code, self.checked) {
+                    (1, false) => val1.wrapping_add(&val2),
+                    (1, true) => val1.checked_add(&val2).ok_or(ErrorKind::Overflow)?,
+                    (_, false) => val1.wrapping_mul(&val2),
+                    (_, true) => val1.checked_mul(&val2).ok_or(ErrorKind::Overflow)?,
+                };
+                self.store(insert_index, new_val);
+                self.index += 4;
+            }
+            3 => {
+                let idx = writable(self.get_index(index + 1, mode1)?)?;
+                let value = self.input.pop_front().ok_or(ErrorKind::NoInput)?;
+                self.store(idx, value);
+                self.index += 2;
+            }
+            4 => {
+                let val = self.get_value(index + 1, mode1)?;
+                self.output.push(val);
+                self.index += 2;
+            }
+            5 | 6 => {
+                let val1 = self.get_value(index + 1, mode1)?;
+                if val1.is_zero() != (instruction.code == 5) {
+                    self.index = self.get_value(index + 2, mode2)?.to_i64().ok_or(ErrorKind::Overflow)?;
+                } else {
+                    self.index += 3;
+                }
+            }
+            7 | 8 => {
+                let val1 = self.get_value(index + 1, mode1)?;
+                let val2 = self.get_value(index + 2, mode2)?;
+                let val3 = writable(self.get_index(index + 3, mode3)?)?;
+                let result = if instruction.code == 7 {
+                    val1 < val2
+                } else {
+                    val1 == val2
+                };
+                self.store(val3, W::from_i64(result as i64));
+                self.index += 4;
+            }
+            9 => {
+                let offset = self.get_value(index + 1, mode1)?.to_i64();
+                self.relative_base = offset
+                    .and_then(|offset| self.relative_base.checked_add(offset))
+                    .ok_or(ErrorKind::Overflow)?;
+                self.index += 2;
+            }
+            99 => self.halted = true,
+            code => {
+                let extension = self.extensions.get(&code).ok_or(ErrorKind::UnknownOpcode(code))?.clone();
+                let params = (0..extension.operands)
+                    .map(|i| self.param(index + 1 + i as i64, instruction.modes[i]))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (memory_len, produced) = (self.memory.len(), self.output.len());
+                let action = match (extension.handler)(self, &params) {
+                    Ok(action) => action,
+                    Err(kind) => {
+                        for (address, old) in std::mem::take(&mut self.writes).into_iter().rev() {
+                            self.poke(address as usize, old);
+                        }
+                        self.memory.truncate(memory_len);
+                        self.output.truncate(produced);
+                        return Err(kind);
+                    }
+                };
+                match action {
+                    Action::Continue => self.index = index + 1 + extension.operands as i64,
+                    Action::Jump(address) => self.index = address,
+                    Action::Halt => self.halted = true,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn param(&self, idx: i64, mode: Mode) -> Result<Param<W>, ErrorKind> {
+        let address = match mode {
+            Mode::Immediate => None,
+            _ => Some(self.get_index(idx, mode)?),
+        };
+        let value = self.read(address.unwrap_or(idx));
+        Ok(Param { mode, value, address })
+    }
+
+    /// Runs until the program needs input, produces an output or halts.
+    /// The machine keeps its state, so it can be given more input with
+    /// [`push_input`](Machine::push_input) and resumed again.
+    pub fn resume(&mut self) -> Result<State<W>, Error> {
+        loop {
+            if self.halted {
+                return Ok(State::Halted);
+            }
+            if self.input.is_empty() && self.read(self.index).to_i64().map(|word| word % 100) == Some(3) {
+                return Ok(State::NeedsInput);
+            }
+            let produced = self.output.len();
+            self.advance()?;
+            if self.output.len() > produced {
+                return Ok(State::Output(self.output.pop().unwrap()));
+            }
+        }
+    }
+
+    /// Runs until the program halts, or needs input that `input` cannot
+    /// give, sending every output value to `output`. Fails with
+    /// [`ErrorKind::OutputClosed`] at the next instruction if `output`
+    /// closes.
+    pub fn run_with<I: Input<W>, O: Output<W>>(&mut self, mut input: I, mut output: O) -> Result<State<W>, Error> {
+        loop {
+            match self.resume()? {
+                State::NeedsInput => match input.read() {
+                    Some(value) => self.push_input(value),
+                    None => return Ok(State::NeedsInput),
+                },
+                State::Output(value) => {
+                    output.write(value);
+                    if output.is_closed() {
+                        let instruction = self.read(self.index).to_i64().unwrap_or(0);
+                        return Err(Error { kind: ErrorKind::OutputClosed, index: self.index, instruction });
+                    }
+                }
+                State::Halted => return Ok(State::Halted),
+            }
+        }
+    }
+
+    /// Runs until the program halts.
+    pub fn run(&mut self) -> Result<(), Error> {
+        while !self.halted {
+            self.advance()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the next compiled block, or steps if there is none to run.
+    fn advance(&mut self) -> Result<(), Error> {
+        match self.block() {
+            Some(block) => self.run_block(&block),
+            None => self.step(),
+        }
+    }
+}
+
+/// Runs a fresh copy of `program` on `inputs` and returns all its output.
+pub fn run_program(program: &[i64], inputs: &[i64]) -> Result<Vec<i64>, Error> {
+    let mut machine = Machine::new(program);
+    for input in inputs {
+        machine.push_input(*input);
+    }
+    machine.run()?;
+    Ok(machine.take_output())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn error(program: &[i64], inputs: &[i64]) -> Error {
+        run_program(program, inputs).unwrap_err()
+    }
+
+    #[test]
+    fn test_parse_instruction() {
+        let instruction = parse_instruction(21002).unwrap();
+        assert_eq!(instruction.code, 2);
+        assert_eq!(instruction.modes, [Mode::Position, Mode::Immediate, Mode::Relative]);
+        assert_eq!(parse_instruction(1302).unwrap_err(), ErrorKind::InvalidMode(3));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut machine = Machine::new(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        machine.run().unwrap();
+        assert_eq!(machine.memory(), vec![3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        let mut machine = Machine::new(&[1002, 4, 3, 4, 33]);
+        machine.run().unwrap();
+        assert_eq!(machine.memory(), vec![1002, 4, 3, 4, 99]);
+    }
+
+    #[test]
+    fn test_compare_with_8() {
+        let program = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        assert_eq!(run_program(&program, &[7]), Ok(vec![999]));
+        assert_eq!(run_program(&program, &[8]), Ok(vec![1000]));
+        assert_eq!(run_program(&program, &[9]), Ok(vec![1001]));
+    }
+
+    #[test]
+    fn test_relative_mode_quine() {
+        let program = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        assert_eq!(run_program(&program, &[]), Ok(program));
+    }
+
+    #[test]
+    fn test_large_numbers() {
+        assert_eq!(run_program(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]), Ok(vec![1219070632396864]));
+        assert_eq!(run_program(&[104, 1125899906842624, 99], &[]), Ok(vec![1125899906842624]));
+    }
+
+    #[test]
+    fn test_write_beyond_program() {
+        let mut machine = Machine::new(&[1101, 2, 3, 10, 99]);
+        machine.run().unwrap();
+        assert_eq!(machine.read(10), 5);
+        assert_eq!(machine.memory().len(), 11);
+    }
+
+    #[test]
+    fn test_memory_grows_with_zeros() {
+        let mut machine = Machine::new(&[1, 2, 3]);
+        assert_eq!(machine.read(1000), 0);
+        assert_eq!(machine.memory().len(), 3);
+        machine.write(6, 7).unwrap();
+        assert_eq!(machine.memory(), vec![1, 2, 3, 0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn test_cache_sees_self_modification() {
+        let program = assemble(
+            "start: out #7
+                    jnz [flag], #done
+                    add #1, #0, [flag]
+                    add #4, #0, [start]
+                    jz #0, #start
+             done:  hlt
+             flag:  data 0",
+        )
+        .unwrap();
+        let mut machine = Machine::new(&program);
+        machine.enable_cache();
+        machine.run().unwrap();
+        assert_eq!(machine.take_output(), vec![7, 0]);
+        assert_eq!(run_program(&program, &[]), Ok(vec![7, 0]));
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut machine = Machine::new(&[3, 9, 1001, 9, 1, 10, 4, 10, 99, 0, 0]);
+        assert_eq!(machine.resume(), Ok(State::NeedsInput));
+        assert_eq!(machine.resume(), Ok(State::NeedsInput));
+        machine.push_input(41);
+        assert_eq!(machine.resume(), Ok(State::Output(42)));
+        assert_eq!(machine.resume(), Ok(State::Halted));
+        assert_eq!(machine.resume(), Ok(State::Halted));
+        assert!(machine.take_output().is_empty());
+    }
+
+    #[test]
+    fn test_resume_keeps_state() {
+        // Doubles every input until it reads 0.
+        let program = vec![3, 100, 1006, 100, 14, 1002, 100, 2, 101, 4, 101, 1105, 1, 0, 99];
+        let mut machine = Machine::new(&program);
+        for input in 1..5 {
+            assert_eq!(machine.resume(), Ok(State::NeedsInput));
+            machine.push_input(input);
+            assert_eq!(machine.resume(), Ok(State::Output(input * 2)));
+        }
+        machine.push_input(0);
+        assert_eq!(machine.resume(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_errors() {
+        let unknown = Error { kind: ErrorKind::UnknownOpcode(42), index: 2, instruction: 42 };
+        assert_eq!(error(&[104, 0, 42], &[]), unknown);
+        assert_eq!(unknown.to_string(), "unknown opcode 42 at 2 (instruction 42)");
+        assert_eq!(error(&[30104, 0, 99], &[]).kind, ErrorKind::InvalidMode(3));
+        assert_eq!(
+            error(&[1101, 1, 1, 0, 11101, 1, 1, 0, 99], &[]),
+            Error { kind: ErrorKind::ImmediateWrite, index: 4, instruction: 11101 }
+        );
+        assert_eq!(error(&[4, -3, 99], &[]).kind, ErrorKind::NegativeAddress(-3));
+        assert_eq!(error(&[109, -5, 204, 1, 99], &[]).kind, ErrorKind::NegativeAddress(-4));
+        assert_eq!(error(&[1105, 1, -1], &[]).kind, ErrorKind::NegativeAddress(-1));
+        assert_eq!(error(&[3, 0, 3, 0, 99], &[7]), Error { kind: ErrorKind::NoInput, index: 2, instruction: 3 });
+        assert_eq!(error(&[1101, 1, 1, 5, 104, 7], &[]).kind, ErrorKind::EndOfMemory);
+        assert_eq!(
+            error(&[1105, 1, 100, 99], &[]),
+            Error { kind: ErrorKind::EndOfMemory, index: 100, instruction: 0 }
+        );
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let far = 1 << 60;
+        let mut machine = Machine::new(&[3, far, 1101, 1, 2, far, 99]);
+        machine.push_input(5);
+        let too_large = Error { kind: ErrorKind::AddressTooLarge(far), index: 0, instruction: 3 };
+        assert_eq!(machine.run(), Err(too_large));
+        assert_eq!(too_large.to_string(), format!("address {} is past the memory limit at 0 (instruction 3)", far));
+        assert_eq!((machine.pending_input(), machine.memory().len()), (1, 7));
+        assert_eq!(machine.write(far, 1), Err(ErrorKind::AddressTooLarge(far)));
+        assert_eq!(machine.write(-1, 1), Err(ErrorKind::NegativeAddress(-1)));
+        machine.write(1, 10).unwrap();
+        machine.write(5, MEMORY_LIMIT as i64 - 1).unwrap();
+        machine.run().unwrap();
+        assert_eq!(machine.memory().len(), MEMORY_LIMIT);
+        assert_eq!(error(&[1101, 1, 2, 5_000_000, 99], &[]).kind, ErrorKind::AddressTooLarge(5_000_000));
+    }
+
+    #[test]
+    fn test_budget() {
+        let program = vec![1101, 1, 2, 9, 104, 7, 1105, 1, 0, 0];
+        let mut machine = Machine::new(&program);
+        machine.set_budget(Some(4));
+        let error = machine.run().unwrap_err();
+        assert_eq!(error, Error { kind: ErrorKind::BudgetExhausted, index: 4, instruction: 104 });
+        assert_eq!(error.to_string(), "step budget exhausted at 4 (instruction 104)");
+        assert_eq!(machine.steps(), 4);
+        assert_eq!(machine.take_output(), vec![7]);
+        machine.set_budget(Some(3));
+        assert_eq!(machine.resume(), Ok(State::Output(7)));
+        assert_eq!(machine.resume().unwrap_err().kind, ErrorKind::BudgetExhausted);
+        assert_eq!(machine.steps(), 7);
+        machine.set_budget(None);
+        assert_eq!(machine.resume(), Ok(State::Output(7)));
+    }
+
+    #[test]
+    fn test_error_leaves_machine_recoverable() {
+        let mut machine = Machine::new(&[3, 5, 4, 5, 99, 0]);
+        assert_eq!(machine.step().unwrap_err().kind, ErrorKind::NoInput);
+        assert_eq!(machine.index(), 0);
+        machine.push_input(8);
+        machine.run().unwrap();
+        assert_eq!(machine.take_output(), vec![8]);
+    }
+}
@@ -0,0 +1,387 @@
+//! The compiled tier: straight-line runs of instructions turned into
+//! closures with their modes decoded and operands bound, so running them
+//! skips the decoding [`Machine::step`] does for every instruction.
+//!
+//! A block starts where the machine first runs it and takes instructions
+//! up to and including the first jump, output or halt. It stops before an
+//! input or anything it cannot compile, which the interpreter runs instead.
+//! A write to the words of a block drops it, and its start is interpreted
+//! from then on; a block that writes to itself stops right after the write.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+use super::{parse_instruction, writable, Machine, Mode};
+use crate::error::{Error, ErrorKind};
+use crate::word::Word;
+
+/// Most instructions in a block, which bounds how far back a write has to
+/// look for blocks it lands in.
+const MAX_OPS: usize = 64;
+const MAX_SPAN: usize = MAX_OPS * 4;
+
+type Op<W> = dyn Fn(&mut Machine<W>) -> Result<(), ErrorKind> + Send + Sync;
+
+pub(super) struct Block<W> {
+    /// Address and instruction word of every instruction, and the closure
+    /// that runs it and moves the instruction pointer on.
+    ops: Vec<(i64, i64, Box<Op<W>>)>,
+    /// Address after the last instruction.
+    end: usize,
+}
+
+enum Slot<W> {
+    NotCompiled,
+    Block(Arc<Block<W>>),
+    /// Nothing to compile here, or a block here was written to.
+    Interpret,
+}
+
+impl<W> Clone for Slot<W> {
+    fn clone(&self) -> Self {
+        match self {
+            Slot::NotCompiled => Slot::NotCompiled,
+            Slot::Block(block) => Slot::Block(block.clone()),
+            Slot::Interpret => Slot::Interpret,
+        }
+    }
+}
+
+pub(super) struct Compiled<W> {
+    /// What to do at each address.
+    slots: Vec<Slot<W>>,
+    /// Number of blocks each address is part of.
+    covered: Vec<u32>,
+    /// Set when a write drops a block, so the running block stops.
+    dropped: bool,
+}
+
+impl<W> Clone for Compiled<W> {
+    fn clone(&self) -> Self {
+        Compiled { slots: self.slots.clone(), covered: self.covered.clone(), dropped: self.dropped }
+    }
+}
+
+impl<W> fmt::Debug for Compiled<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let blocks = self.slots.iter().filter(|slot| matches!(slot, Slot::Block(_))).count();
+        write!(f, "Compiled {{ blocks: {} }}", blocks)
+    }
+}
+
+impl<W> Compiled<W> {
+    pub(super) fn new() -> Compiled<W> {
+        Compiled { slots: Vec::new(), covered: Vec::new(), dropped: false }
+    }
+
+    /// Drops every block that `address` is part of.
+    pub(super) fn written(&mut self, address: usize) {
+        if self.covered.get(address).is_none_or(|blocks| *blocks == 0) {
+            return;
+        }
+        for start in address.saturating_sub(MAX_SPAN)..=address {
+            let end = match self.slots.get(start) {
+                Some(Slot::Block(block)) if block.end > address => block.end,
+                _ => continue,
+            };
+            self.slots[start] = Slot::Interpret;
+            self.covered[start..end].iter_mut().for_each(|blocks| *blocks -= 1);
+            self.dropped = true;
+        }
+    }
+}
+
+/// An operand with its mode decoded. Immediates are kept as `i64` so the
+/// closures do not hold words, which need not be `Sync`.
+#[derive(Clone, Copy)]
+enum Operand {
+    Immediate(i64),
+    Position(usize),
+    Relative(i64),
+}
+
+impl Operand {
+    fn value<W: Word>(self, machine: &Machine<W>) -> Result<W, ErrorKind> {
+        let address = match self {
+            Operand::Immediate(value) => return Ok(W::from_i64(value)),
+            Operand::Position(address) => address,
+            Operand::Relative(offset) => relative(machine, offset)?,
+        };
+        Ok(machine.memory.get(address).cloned().unwrap_or_default())
+    }
+
+    /// The address to write to.
+    fn address<W>(self, machine: &Machine<W>) -> Result<usize, ErrorKind> {
+        let address = match self {
+            Operand::Immediate(_) => return Err(ErrorKind::ImmediateWrite),
+            Operand::Position(address) => address,
+            Operand::Relative(offset) => relative(machine, offset)?,
+        };
+        writable(address as i64).map(|address| address as usize)
+    }
+}
+
+fn relative<W>(machine: &Machine<W>, offset: i64) -> Result<usize, ErrorKind> {
+    let address = offset.checked_add(machine.relative_base).ok_or(ErrorKind::Overflow)?;
+    if address < 0 {
+        return Err(ErrorKind::NegativeAddress(address));
+    }
+    Ok(address as usize)
+}
+
+impl<W: Word> Machine<W> {
+    /// Operand `i` of the instruction at `index`, or `None` if it is past
+    /// the end of memory or would fail whenever it is used, which is left
+    /// to the interpreter to report.
+    fn operand(&self, index: usize, i: usize, mode: Mode) -> Option<Operand> {
+        let word = self.memory.get(index + 1 + i)?.to_i64()?;
+        Some(match mode {
+            Mode::Immediate => Operand::Immediate(word),
+            Mode::Position => Operand::Position(usize::try_from(word).ok()?),
+            Mode::Relative => Operand::Relative(word),
+        })
+    }
+
+    /// The instruction word at `index`, the closure that runs it, its size
+    /// and whether it ends a block.
+    fn compile_op(&self, index: usize) -> Option<(i64, Box<Op<W>>, usize, bool)> {
+        let word = self.memory[index].to_i64()?;
+        let instruction = parse_instruction(word).ok()?;
+        let operand = |i: usize| self.operand(index, i, instruction.modes[i]);
+        let size = match instruction.code {
+            1 | 2 | 7 | 8 => 4,
+            5 | 6 => 3,
+            4 | 9 => 2,
+            99 => 1,
+            _ => return None,
+        };
+        let next = (index + size) as i64;
+        let op: Box<Op<W>> = match instruction.code {
+            1 | 2 => {
+                let (a, b, target) = (operand(0)?, operand(1)?, operand(2)?);
+                let multiply = instruction.code == 2;
+                Box::new(move |m| {
+                    let (a, b) = (a.value::<W>(m)?, b.value(m)?);
+                    let address = target.address(m)?;
+                    let result = match (multiply, m.checked) {
+                        (false, false) => a.wrapping_add(&b),
+                        (false, true) => a.checked_add(&b).ok_or(ErrorKind::Overflow)?,
+                        (true, false) => a.wrapping_mul(&b),
+                        (true, true) => a.checked_mul(&b).ok_or(ErrorKind::Overflow)?,
+                    };
+                    m.poke(address, result);
+                    m.index = next;
+                    Ok(())
+                })
+            }
+            7 | 8 => {
+                let (a, b, target) = (operand(0)?, operand(1)?, operand(2)?);
+                let less = instruction.code == 7;
+                Box::new(move |m| {
+                    let (a, b) = (a.value::<W>(m)?, b.value(m)?);
+                    let address = target.address(m)?;
+                    let result = if less { a < b } else { a == b };
+                    m.poke(address, W::from_i64(result as i64));
+                    m.index = next;
+                    Ok(())
+                })
+            }
+            4 => {
+                let value = operand(0)?;
+                Box::new(move |m| {
+                    let value = value.value(m)?;
+                    m.output.push(value);
+                    m.index = next;
+                    Ok(())
+                })
+            }
+            5 | 6 => {
+                let (condition, target) = (operand(0)?, operand(1)?);
+                let if_zero = instruction.code == 6;
+                Box::new(move |m| {
+                    m.index = if condition.value::<W>(m)?.is_zero() == if_zero {
+                        target.value::<W>(m)?.to_i64().ok_or(ErrorKind::Overflow)?
+                    } else {
+                        next
+                    };
+                    Ok(())
+                })
+            }
+            9 => {
+                let offset = operand(0)?;
+                Box::new(move |m| {
+                    let offset = offset.value::<W>(m)?.to_i64();
+                    m.relative_base = offset
+                        .and_then(|offset| m.relative_base.checked_add(offset))
+                        .ok_or(ErrorKind::Overflow)?;
+                    m.index = next;
+                    Ok(())
+                })
+            }
+            _ => Box::new(|m| {
+                m.halted = true;
+                Ok(())
+            }),
+        };
+        Some((word, op, size, matches!(instruction.code, 4 | 5 | 6 | 99)))
+    }
+
+    fn compile(&self, start: usize) -> Option<Block<W>> {
+        let mut ops = Vec::new();
+        let mut index = start;
+        while ops.len() < MAX_OPS && index < self.memory.len() {
+            let (word, op, size, last) = match self.compile_op(index) {
+                Some(compiled) => compiled,
+                None => break,
+            };
+            ops.push((index as i64, word, op));
+            index += size;
+            if last {
+                break;
+            }
+        }
+        if ops.is_empty() {
+            return None;
+        }
+        Some(Block { ops, end: index })
+    }
+
+    /// The block starting at the instruction pointer, compiling it the
+    /// first time it runs, or `None` if the next instruction has to be
+    /// stepped: compilation is off, something watches every step, the
+    /// block would overrun the budget or there is nothing to compile.
+    pub(super) fn block(&mut self) -> Option<Arc<Block<W>>> {
+        if self.compiled.is_none()
+            || self.halted
+            || self.history.is_some()
+            || self.profile.is_some()
+            || self.watch.is_some()
+            || self.trace.is_some()
+            || self.repeats.is_some()
+        {
+            return None;
+        }
+        let index = usize::try_from(self.index).ok().filter(|index| *index < self.memory.len())?;
+        let block = match self.compiled.as_ref()?.slots.get(index) {
+            Some(Slot::Block(block)) => block.clone(),
+            Some(Slot::Interpret) => return None,
+            Some(Slot::NotCompiled) | None => self.compile_at(index)?,
+        };
+        let steps = self.steps + block.ops.len() as u64;
+        if self.budget.is_some_and(|budget| steps > budget) {
+            return None;
+        }
+        Some(block)
+    }
+
+    fn compile_at(&mut self, index: usize) -> Option<Arc<Block<W>>> {
+        let block = self.compile(index).map(Arc::new);
+        let compiled = self.compiled.as_mut()?;
+        if index >= compiled.slots.len() {
+            compiled.slots.resize(index + 1, Slot::NotCompiled);
+        }
+        compiled.slots[index] = match &block {
+            Some(block) => {
+                if block.end > compiled.covered.len() {
+                    compiled.covered.resize(block.end, 0);
+                }
+                compiled.covered[index..block.end].iter_mut().for_each(|blocks| *blocks += 1);
+                Slot::Block(block.clone())
+            }
+            None => Slot::Interpret,
+        };
+        block
+    }
+
+    /// Runs `block`, which starts at the instruction pointer, stopping early
+    /// if it writes to compiled code. On error the machine stands at the
+    /// failing instruction, as [`step`](Machine::step) leaves it.
+    pub(super) fn run_block(&mut self, block: &Block<W>) -> Result<(), Error> {
+        if let Some(compiled) = self.compiled.as_mut() {
+            compiled.dropped = false;
+        }
+        for (index, word, op) in &block.ops {
+            if let Err(kind) = op(self) {
+                return Err(Error { kind, index: *index, instruction: *word });
+            }
+            self.steps += 1;
+            if self.compiled.as_ref().is_some_and(|compiled| compiled.dropped) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::assemble;
+    use crate::error::{Error, ErrorKind};
+    use crate::machine::{Machine, State};
+
+    fn run(program: &[i64], compiled: bool) -> (Vec<i64>, Vec<i64>, u64) {
+        let mut machine = Machine::new(program);
+        if compiled {
+            machine.enable_compilation();
+        }
+        machine.run().unwrap();
+        (machine.take_output(), machine.memory(), machine.steps())
+    }
+
+    #[test]
+    fn test_self_modification() {
+        // Every time round, the second add patches the immediate of the add
+        // after it, in the middle of the block that is running and of the
+        // block compiled at `next` the time before.
+        let program = assemble(
+            "loop: add [n], #1, [n]
+                   add #0, [n], [next+1]
+             next: add #5, #0, [x]
+                   out [x]
+                   add [n], #-3, [t]
+                   jnz [t], #loop
+                   hlt
+             n:    data 0
+             x:    data 0
+             t:    data 0",
+        )
+        .unwrap();
+        let expected = run(&program, false);
+        assert_eq!(expected.0, vec![1, 2, 3]);
+        assert_eq!(run(&program, true), expected);
+    }
+
+    #[test]
+    fn test_error_in_block() {
+        // Sets the relative base to -9, adds and then reads `[rb+0]`.
+        let mut machine = Machine::new(&[109, -9, 1101, 1, 2, 15, 2201, 0, 0, 0, 99, 0, 0, 0, 0, 0]);
+        machine.enable_compilation();
+        let error = Error { kind: ErrorKind::NegativeAddress(-9), index: 6, instruction: 2201 };
+        assert_eq!(machine.run(), Err(error));
+        assert_eq!((machine.index(), machine.steps(), machine.read(15)), (6, 2, 3));
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut machine = Machine::new(&[1101, 1, 2, 9, 1101, 1, 2, 9, 99, 0]);
+        machine.enable_compilation();
+        machine.set_budget(Some(1));
+        assert_eq!(machine.run().unwrap_err().kind, ErrorKind::BudgetExhausted);
+        assert_eq!((machine.index(), machine.steps()), (4, 1));
+        machine.set_budget(None);
+        assert_eq!(machine.run(), Ok(()));
+        assert_eq!(machine.steps(), 3);
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut machine = Machine::new(&[3, 9, 1002, 9, 2, 9, 4, 9, 99, 0]);
+        machine.enable_compilation();
+        assert_eq!(machine.resume(), Ok(State::NeedsInput));
+        machine.push_input(21);
+        assert_eq!(machine.resume(), Ok(State::Output(42)));
+        assert_eq!(machine.resume(), Ok(State::Halted));
+        assert_eq!(machine.steps(), 4);
+    }
+}
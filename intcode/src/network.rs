@@ -0,0 +1,302 @@
+//! Machines wired into a network that passes packets, as in day 23.
+//!
+//! Every machine is given its address as its first input. It sends a packet
+//! by outputting `destination, x, y` and receives one by reading `x, y`;
+//! when nothing is waiting for it, it reads `-1` instead. Addresses with no
+//! machine can have a hook that sees the packets sent there.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+
+use crate::error::Error;
+use crate::machine::{Machine, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// Whether the network keeps running after a hook has seen a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// Why [`Network::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// Every machine is waiting for a packet and none are on their way.
+    Idle,
+    /// A hook stopped the network after seeing this packet.
+    Hook(Packet),
+    /// Every machine has halted.
+    Halted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    /// The machine at this address failed.
+    Machine(i64, Error),
+    /// The packet went to an address with neither a machine nor a hook.
+    UnknownAddress(Packet),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Machine(address, error) => write!(f, "machine {}: {}", address, error),
+            NetworkError::UnknownAddress(packet) => write!(
+                f,
+                "packet ({}, {}) sent to address {}, which has no machine",
+                packet.x, packet.y, packet.destination
+            ),
+        }
+    }
+}
+
+impl error::Error for NetworkError {}
+
+type Hook = dyn FnMut(Packet) -> Control;
+
+struct Node {
+    machine: Machine,
+    /// Packets delivered but not read yet, as `(x, y)`.
+    queue: VecDeque<(i64, i64)>,
+    /// Output of a packet still being sent.
+    sending: Vec<i64>,
+}
+
+/// How a machine's turn went.
+enum Turn {
+    /// Nothing was sent or received.
+    Quiet,
+    Busy,
+    Halted,
+    Stopped(Packet),
+}
+
+/// A network of machines with addresses `0..n`, run one machine at a time.
+pub struct Network {
+    nodes: Vec<Node>,
+    hooks: BTreeMap<i64, Box<Hook>>,
+    /// Address of the machine whose turn is next.
+    next: usize,
+    /// Turns in a row in which nothing was sent or received.
+    quiet: usize,
+}
+
+impl Network {
+    /// `size` machines running `program`.
+    pub fn new(program: &[i64], size: usize) -> Network {
+        Network::from_machines((0..size).map(|_| Machine::new(program)).collect())
+    }
+
+    /// A network of the given machines, each addressed by its position, for
+    /// machines set up with a budget or different programs.
+    pub fn from_machines(machines: Vec<Machine>) -> Network {
+        let nodes = machines
+            .into_iter()
+            .enumerate()
+            .map(|(address, mut machine)| {
+                machine.push_input(address as i64);
+                Node { machine, queue: VecDeque::new(), sending: Vec::new() }
+            })
+            .collect();
+        Network { nodes, hooks: BTreeMap::new(), next: 0, quiet: 0 }
+    }
+
+    /// Calls `hook` with every packet sent to `address`. Panics if a machine
+    /// has that address.
+    pub fn hook<F>(&mut self, address: i64, hook: F)
+    where
+        F: FnMut(Packet) -> Control + 'static,
+    {
+        assert!(self.node(address).is_none(), "address {} belongs to a machine", address);
+        self.hooks.insert(address, Box::new(hook));
+    }
+
+    /// The machine at `address`.
+    pub fn machine(&self, address: i64) -> Option<&Machine> {
+        self.node(address).map(|node| &node.machine)
+    }
+
+    fn node(&self, address: i64) -> Option<&Node> {
+        usize::try_from(address).ok().and_then(|address| self.nodes.get(address))
+    }
+
+    /// Delivers `packet` to the machine it is addressed to, as if another
+    /// machine had sent it.
+    pub fn send(&mut self, packet: Packet) -> Result<(), NetworkError> {
+        let node = usize::try_from(packet.destination).ok().and_then(|address| self.nodes.get_mut(address));
+        node.ok_or(NetworkError::UnknownAddress(packet))?.queue.push_back((packet.x, packet.y));
+        self.quiet = 0;
+        Ok(())
+    }
+
+    fn route(&mut self, packet: Packet) -> Result<Control, NetworkError> {
+        match self.hooks.get_mut(&packet.destination) {
+            Some(hook) => Ok(hook(packet)),
+            None => self.send(packet).map(|_| Control::Continue),
+        }
+    }
+
+    /// Runs the machine at `address` until it needs input with no packet
+    /// waiting, after giving it at most one `-1`.
+    fn turn(&mut self, address: usize) -> Result<Turn, NetworkError> {
+        let mut turn = Turn::Quiet;
+        let mut polled = false;
+        loop {
+            let node = &mut self.nodes[address];
+            let state = node.machine.resume().map_err(|e| NetworkError::Machine(address as i64, e))?;
+            match state {
+                State::NeedsInput => match node.queue.pop_front() {
+                    Some((x, y)) => {
+                        node.machine.push_input(x);
+                        node.machine.push_input(y);
+                        turn = Turn::Busy;
+                    }
+                    None if polled => return Ok(turn),
+                    None => {
+                        node.machine.push_input(-1);
+                        polled = true;
+                    }
+                },
+                State::Output(value) => {
+                    node.sending.push(value);
+                    if let [destination, x, y] = node.sending[..] {
+                        node.sending.clear();
+                        let packet = Packet { destination, x, y };
+                        if self.route(packet)? == Control::Stop {
+                            return Ok(Turn::Stopped(packet));
+                        }
+                        turn = Turn::Busy;
+                    }
+                }
+                State::Halted => return Ok(Turn::Halted),
+            }
+        }
+    }
+
+    /// Gives the machines turns in order of address until the network is
+    /// idle, a hook stops it or every machine has halted. Can be called
+    /// again to carry on, typically after [`send`](Network::send)ing a
+    /// packet to wake an idle network up.
+    ///
+    /// A machine that never needs input and never halts keeps the others
+    /// from running; give such machines a budget.
+    pub fn run(&mut self) -> Result<Stop, NetworkError> {
+        loop {
+            let running = self.nodes.iter().filter(|node| !node.machine.is_halted()).count();
+            if running == 0 {
+                return Ok(Stop::Halted);
+            }
+            if self.next == self.nodes.len() {
+                self.next = 0;
+            }
+            let address = self.next;
+            self.next += 1;
+            match self.turn(address)? {
+                Turn::Quiet => self.quiet += 1,
+                Turn::Busy => self.quiet = 0,
+                Turn::Halted => {}
+                Turn::Stopped(packet) => {
+                    self.quiet = 0;
+                    return Ok(Stop::Hook(packet));
+                }
+            }
+            if self.quiet >= running && self.nodes.iter().all(|node| node.queue.is_empty()) {
+                self.quiet = 0;
+                return Ok(Stop::Idle);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::error::ErrorKind;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // Machine 0 sends (0, 100) to machine 1. Every machine passes what it
+    // receives on to the next with x + 1 and y + its address, the last one
+    // to 255.
+    fn relay() -> Vec<i64> {
+        assemble(
+            "        in [addr]
+                     jnz [addr], #loop
+                     out #1
+                     out #0
+                     out #100
+             loop:   in [x]
+                     eq [x], #-1, [t]
+                     jnz [t], #loop
+                     in [y]
+                     add [x], #1, [x]
+                     add [y], [addr], [y]
+                     add [addr], #1, [dest]
+                     eq [addr], #3, [t]
+                     jz [t], #send
+                     add #255, #0, [dest]
+             send:   out [dest]
+                     out [x]
+                     out [y]
+                     jnz #1, #loop
+             addr:   data 0
+             x:      data 0
+             y:      data 0
+             t:      data 0
+             dest:   data 0",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hook_and_idle() {
+        let mut network = Network::new(&relay(), 4);
+        network.hook(255, |_| Control::Stop);
+        assert_eq!(network.run(), Ok(Stop::Hook(Packet { destination: 255, x: 3, y: 106 })));
+        assert_eq!(network.run(), Ok(Stop::Idle));
+        assert_eq!(network.run(), Ok(Stop::Idle));
+        network.send(Packet { destination: 0, x: 10, y: 0 }).unwrap();
+        assert_eq!(network.run(), Ok(Stop::Hook(Packet { destination: 255, x: 14, y: 6 })));
+    }
+
+    #[test]
+    fn test_hook_continues() {
+        let seen = Rc::new(Cell::new(None));
+        let mut network = Network::new(&relay(), 4);
+        let last = seen.clone();
+        network.hook(255, move |packet| {
+            last.set(Some(packet));
+            Control::Continue
+        });
+        assert_eq!(network.run(), Ok(Stop::Idle));
+        assert_eq!(seen.get(), Some(Packet { destination: 255, x: 3, y: 106 }));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = Network::new(&relay(), 4).run().unwrap_err();
+        assert_eq!(error, NetworkError::UnknownAddress(Packet { destination: 255, x: 3, y: 106 }));
+        assert_eq!(error.to_string(), "packet (3, 106) sent to address 255, which has no machine");
+        let mut network = Network::from_machines(vec![Machine::new(&relay()), Machine::new(&[3, 0, 4, 0, 55])]);
+        let error = Error { kind: ErrorKind::UnknownOpcode(55), index: 4, instruction: 55 };
+        assert_eq!(network.run(), Err(NetworkError::Machine(1, error)));
+        assert_eq!(Network::new(&[3, 3, 99, 0], 3).run(), Ok(Stop::Halted));
+        assert_eq!(network.machine(1).map(Machine::index), Some(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "address 2 belongs to a machine")]
+    fn test_machine_addresses_cannot_be_hooked() {
+        Network::new(&[99], 3).hook(2, |_| Control::Continue);
+    }
+}
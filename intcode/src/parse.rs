@@ -0,0 +1,164 @@
+//! Reading Intcode program files.
+//!
+//! Numbers are separated by commas, line breaks or both, so programs may be
+//! split over several lines and end with a trailing comma. Spaces and tabs
+//! around numbers are ignored and `#` starts a comment that runs to the end
+//! of the line.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::word::Word;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The token is not a number that fits in the word type.
+    InvalidNumber,
+    /// Two commas with no number between them, or a comma before the first number.
+    MissingNumber,
+    /// Two numbers on the same line with no comma between them.
+    MissingComma,
+    /// The file holds no numbers at all.
+    Empty,
+}
+
+/// Where parsing failed: 1-based line and column of the offending token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.kind == ParseErrorKind::Empty {
+            return write!(f, "the program is empty");
+        }
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match self.kind {
+            ParseErrorKind::InvalidNumber => write!(f, "invalid number {:?}", self.token),
+            ParseErrorKind::MissingNumber => write!(f, "expected a number before {:?}", self.token),
+            ParseErrorKind::MissingComma => write!(f, "expected a comma before {:?}", self.token),
+            ParseErrorKind::Empty => Ok(()),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Last {
+    Start,
+    Number,
+    Comma,
+}
+
+/// Parses the text of a program file.
+pub fn parse_program(text: &str) -> Result<Vec<i64>, ParseError> {
+    parse_words(text)
+}
+
+/// Parses the text of a program file into any word type, for programs
+/// with numbers too big for an `i64`.
+pub fn parse_words<W: Word>(text: &str) -> Result<Vec<W>, ParseError> {
+    let mut program = Vec::new();
+    let mut last = Last::Start;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let chars: Vec<char> = line.chars().collect();
+        let mut column = 0;
+        let mut same_line = false;
+        while column < chars.len() {
+            let c = chars[column];
+            let error = |kind, token: String| ParseError { kind, line: number + 1, column: column + 1, token };
+            if c.is_whitespace() {
+                column += 1;
+            } else if c == ',' {
+                if last != Last::Number {
+                    return Err(error(ParseErrorKind::MissingNumber, ",".to_string()));
+                }
+                last = Last::Comma;
+                column += 1;
+            } else {
+                let end = (column..chars.len())
+                    .find(|i| chars[*i] == ',' || chars[*i].is_whitespace())
+                    .unwrap_or(chars.len());
+                let token: String = chars[column..end].iter().collect();
+                if last == Last::Number && same_line {
+                    return Err(error(ParseErrorKind::MissingComma, token));
+                }
+                match token.parse::<W>() {
+                    Ok(value) => program.push(value),
+                    Err(_) => return Err(error(ParseErrorKind::InvalidNumber, token)),
+                }
+                last = Last::Number;
+                column = end;
+            }
+            same_line = last == Last::Number;
+        }
+    }
+    if program.is_empty() {
+        return Err(ParseError {
+            kind: ParseErrorKind::Empty,
+            line: 1,
+            column: 1,
+            token: String::new(),
+        });
+    }
+    Ok(program)
+}
+
+/// Reads and parses a program file. Parse errors come back as
+/// [`io::ErrorKind::InvalidData`] wrapping the [`ParseError`].
+pub fn read_program<P: AsRef<Path>>(path: P) -> io::Result<Vec<i64>> {
+    let text = fs::read_to_string(path)?;
+    parse_program(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> ParseError {
+        parse_program(text).unwrap_err()
+    }
+
+    #[test]
+    fn test_layouts() {
+        assert_eq!(parse_program("1,9,10,3,2,3,11,0,99,30,40,50\n"), Ok(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]));
+        assert_eq!(parse_program(" 1, -2 ,\t3,\r\n"), Ok(vec![1, -2, 3]));
+        assert_eq!(parse_program("1,2,\n3,4,\n"), Ok(vec![1, 2, 3, 4]));
+        assert_eq!(parse_program("1,2\n3\n,4"), Ok(vec![1, 2, 3, 4]));
+        assert_eq!(parse_program("# day5 example\n3,0, # read\n4,0,99 # echo\n"), Ok(vec![3, 0, 4, 0, 99]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let e = error("1,2,\n3,x4,5");
+        assert_eq!(e, ParseError { kind: ParseErrorKind::InvalidNumber, line: 2, column: 3, token: "x4".to_string() });
+        assert_eq!(e.to_string(), "line 2, column 3: invalid number \"x4\"");
+        assert_eq!(error("1,,2").to_string(), "line 1, column 3: expected a number before \",\"");
+        assert_eq!(error(",1").kind, ParseErrorKind::MissingNumber);
+        assert_eq!(error("1,2\n,,3").column, 2);
+        assert_eq!(error("1, 2 3").to_string(), "line 1, column 6: expected a comma before \"3\"");
+        assert_eq!(error("99999999999999999999").kind, ParseErrorKind::InvalidNumber);
+        assert_eq!(error("").kind, ParseErrorKind::Empty);
+        assert_eq!(error("  # nothing here\n\n").to_string(), "the program is empty");
+    }
+
+    #[test]
+    fn test_read_program() {
+        let path = std::env::temp_dir().join(format!("intcode-parse-{}.txt", std::process::id()));
+        fs::write(&path, "1,2\nz").unwrap();
+        let e = read_program(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "line 2, column 1: invalid number \"z\"");
+        assert!(read_program("/nonexistent/program.txt").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Execution counts per address and per opcode, and memory traffic per
+//! address, collected by [`Machine::enable_profiling`](crate::Machine::enable_profiling).
+
+use std::collections::BTreeMap;
+
+use crate::asm::MNEMONICS;
+use crate::disasm::{decode, disassemble, Decoded};
+
+/// Addresses below this are counted in a vector, the rest in a map, so a
+/// program reading far past the end of its memory does not make the profile
+/// allocate a counter for every address in between.
+const NEAR: usize = 1 << 20;
+
+/// Counts per address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Counts {
+    near: Vec<u64>,
+    far: BTreeMap<usize, u64>,
+}
+
+impl Counts {
+    fn count(&mut self, address: i64) {
+        if address < 0 {
+            return;
+        }
+        let address = address as usize;
+        if address >= NEAR {
+            *self.far.entry(address).or_insert(0) += 1;
+            return;
+        }
+        if address >= self.near.len() {
+            self.near.resize(address + 1, 0);
+        }
+        self.near[address] += 1;
+    }
+
+    fn get(&self, address: usize) -> u64 {
+        match self.near.get(address) {
+            Some(count) => *count,
+            None => *self.far.get(&address).unwrap_or(&0),
+        }
+    }
+
+    /// Addresses counted at least once, with their counts, lowest first.
+    fn iter(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        let near = self.near.iter().enumerate().filter(|(_, count)| **count > 0);
+        near.map(|(address, count)| (address, *count)).chain(self.far.iter().map(|(a, c)| (*a, *c)))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    executions: Counts,
+    opcodes: BTreeMap<i64, u64>,
+    reads: Counts,
+    writes: Counts,
+    steps: u64,
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+impl Profile {
+    pub(crate) fn record(&mut self, index: i64, code: i64, reads: &[i64], writes: &[i64]) {
+        self.steps += 1;
+        self.executions.count(index);
+        *self.opcodes.entry(code).or_insert(0) += 1;
+        for address in reads {
+            self.reads.count(*address);
+        }
+        for address in writes {
+            self.writes.count(*address);
+        }
+    }
+
+    /// Total number of instructions executed.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// How often the instruction at `address` was executed.
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executions.get(address)
+    }
+
+    /// How often an operand was read from `address`.
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(address)
+    }
+
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(address)
+    }
+
+    /// Executions per opcode, most executed first.
+    pub fn opcodes(&self) -> Vec<(i64, u64)> {
+        let mut opcodes: Vec<(i64, u64)> = self.opcodes.iter().map(|(code, count)| (*code, *count)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        opcodes
+    }
+
+    /// Instruction addresses by how often they ran, most executed first.
+    pub fn hotspots(&self) -> Vec<(usize, u64)> {
+        let mut hotspots: Vec<(usize, u64)> = self.executions.iter().collect();
+        hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hotspots
+    }
+
+    /// Addresses of the instructions the disassembler finds in `program`
+    /// that never ran.
+    pub fn never_ran(&self, program: &[i64]) -> Vec<usize> {
+        disassemble(program)
+            .into_iter()
+            .filter(|line| line.decoded != Decoded::Data && self.executions(line.address) == 0)
+            .map(|line| line.address)
+            .collect()
+    }
+
+    /// A text report with the `top` hottest instructions, the opcode mix
+    /// and which instructions of `program` never ran.
+    pub fn report(&self, program: &[i64], top: usize) -> String {
+        let mut lines = vec![format!("{} instructions executed", self.steps), String::new()];
+        lines.push("hotspots:".to_string());
+        lines.push(format!("{:>8} {:>12} {:>7}  instruction", "address", "count", "share"));
+        for (address, count) in self.hotspots().into_iter().take(top) {
+            let text = decode(program, address).map(|d| d.to_string()).unwrap_or_default();
+            lines.push(format!(
+                "{:>8} {:>12} {:>6.1}%  {}",
+                address,
+                count,
+                percent(count, self.steps),
+                text
+            ));
+        }
+        lines.push(String::new());
+        lines.push("opcodes:".to_string());
+        for (code, count) in self.opcodes() {
+            let name = MNEMONICS.iter().find(|(_, c, _)| *c == code).map_or("?", |(m, _, _)| *m);
+            lines.push(format!("{:>8} {:>12} {:>6.1}%", name, count, percent(count, self.steps)));
+        }
+        lines.push(String::new());
+        let total = disassemble(program).iter().filter(|line| line.decoded != Decoded::Data).count() as u64;
+        let never_ran = self.never_ran(program);
+        let ran = total - never_ran.len() as u64;
+        lines.push(format!("coverage: {} of {} instructions ran ({:.1}%)", ran, total, percent(ran, total)));
+        if !never_ran.is_empty() {
+            let addresses: Vec<String> = never_ran.iter().map(|a| a.to_string()).collect();
+            lines.push(format!("never ran: {}", addresses.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::assemble;
+    use crate::machine::Machine;
+
+    fn program() -> Vec<i64> {
+        assemble(
+            "loop: add [n], #-1, [n]
+                   jnz [n], #loop
+                   jz [n], #done
+                   out #1
+             done: hlt
+             n:    data 3",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_counts() {
+        let program = program();
+        let mut machine = Machine::new(&program);
+        assert!(machine.profile().is_none());
+        machine.enable_profiling();
+        machine.run().unwrap();
+        let profile = machine.profile().unwrap();
+        assert_eq!(profile.steps(), 3 + 3 + 1 + 1);
+        assert_eq!(profile.executions(0), 3);
+        assert_eq!(profile.executions(7), 1);
+        assert_eq!(profile.executions(10), 0);
+        assert_eq!(profile.hotspots(), vec![(0, 3), (4, 3), (7, 1), (12, 1)]);
+        assert_eq!(profile.opcodes(), vec![(1, 3), (5, 3), (6, 1), (99, 1)]);
+        // add reads n once and writes it, jnz and jz read it
+        assert_eq!(profile.reads(13), 3 + 3 + 1);
+        assert_eq!(profile.writes(13), 3);
+        assert_eq!(profile.reads(0), 0);
+        assert_eq!(profile.never_ran(&program), vec![10]);
+    }
+
+    #[test]
+    fn test_far_reads() {
+        let mut machine = Machine::new(&[1, 1 << 60, 6, 7, 4, 1 << 40, 99, 0]);
+        machine.enable_profiling();
+        machine.run().unwrap();
+        let profile = machine.profile().unwrap();
+        assert_eq!((profile.reads(1 << 60), profile.reads(1 << 40), profile.reads(6)), (1, 1, 1));
+        assert_eq!(profile.writes(7), 1);
+    }
+
+    #[test]
+    fn test_report() {
+        let program = program();
+        let mut machine = Machine::new(&program);
+        machine.enable_profiling();
+        machine.run().unwrap();
+        let report = machine.profile().unwrap().report(&program, 2);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "8 instructions executed");
+        assert_eq!(lines[4], "       0            3   37.5%  add [13], #-1, [13]");
+        assert_eq!(lines[5], "       4            3   37.5%  jnz [13], #0");
+        assert_eq!(lines[8], "     add            3   37.5%");
+        assert!(report.contains("coverage: 4 of 5 instructions ran (80.0%)\nnever ran: 10"));
+    }
+}
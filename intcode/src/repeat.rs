@@ -0,0 +1,177 @@
+//! Proving that a program will never halt by catching it in a state it has
+//! been in before.
+//!
+//! The machine is deterministic, so if it reaches the same memory,
+//! instruction pointer and relative base twice without reading input in
+//! between, it will keep going round the same cycle forever. Checkpoints are
+//! taken at doubling intervals (Brent's cycle detection), and memory is
+//! summarised by a hash kept up to date on every write, so a check costs a
+//! few comparisons per instruction and memory is only compared in full when
+//! everything else matches.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+use crate::word::Word;
+
+/// Hash contribution of one memory cell. Zero cells contribute nothing, so
+/// growing memory with zeros leaves the hash unchanged.
+fn term<W: Word>(address: usize, value: &W) -> u64 {
+    if value.is_zero() {
+        return 0;
+    }
+    let bits = match value.to_i64() {
+        Some(value) => value as u64,
+        None => {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+    };
+    let mut x = (address as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ bits;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Whether two memories hold the same values, counting missing cells as 0.
+fn same_memory<W: Word>(a: &[W], b: &[W]) -> bool {
+    let common = a.len().min(b.len());
+    a[..common] == b[..common] && a[common..].iter().chain(&b[common..]).all(Word::is_zero)
+}
+
+#[derive(Debug, Clone)]
+struct Checkpoint<W> {
+    index: i64,
+    relative_base: i64,
+    hash: u64,
+    memory: Vec<W>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RepeatDetector<W> {
+    hash: u64,
+    checkpoint: Option<Checkpoint<W>>,
+    /// Checks left before the next checkpoint, and the interval after that.
+    remaining: u64,
+    interval: u64,
+}
+
+impl<W: Word> RepeatDetector<W> {
+    pub(crate) fn new(memory: &[W]) -> RepeatDetector<W> {
+        let hash = memory
+            .iter()
+            .enumerate()
+            .fold(0u64, |hash, (address, value)| hash.wrapping_add(term(address, value)));
+        RepeatDetector {
+            hash,
+            checkpoint: None,
+            remaining: 0,
+            interval: 1,
+        }
+    }
+
+    /// Keeps the memory hash current when `address` changes from `old` to `new`.
+    pub(crate) fn update(&mut self, address: usize, old: &W, new: &W) {
+        self.hash = self.hash.wrapping_sub(term(address, old)).wrapping_add(term(address, new));
+    }
+
+    /// Forgets the checkpoint, after input or changes from outside the
+    /// program that break the chain of deterministic steps.
+    pub(crate) fn reset(&mut self) {
+        self.checkpoint = None;
+    }
+
+    /// Called before each instruction. Returns true if the machine is back
+    /// in the state of the last checkpoint.
+    pub(crate) fn repeats(&mut self, index: i64, relative_base: i64, memory: &[W]) -> bool {
+        if let Some(checkpoint) = &self.checkpoint {
+            if checkpoint.index == index
+                && checkpoint.relative_base == relative_base
+                && checkpoint.hash == self.hash
+                && same_memory(&checkpoint.memory, memory)
+            {
+                return true;
+            }
+        }
+        if self.checkpoint.is_none() || self.remaining == 0 {
+            self.checkpoint = Some(Checkpoint {
+                index,
+                relative_base,
+                hash: self.hash,
+                memory: memory.to_vec(),
+            });
+            self.remaining = self.interval;
+            self.interval *= 2;
+        }
+        self.remaining -= 1;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::error::ErrorKind;
+    use crate::machine::{run_program, Machine, State};
+
+    #[test]
+    fn test_same_memory() {
+        assert!(same_memory::<i64>(&[1, 2], &[1, 2, 0, 0]));
+        assert!(!same_memory::<i64>(&[1, 2, 3], &[1, 2]));
+        let mut detector = RepeatDetector::<i64>::new(&[1, 0, 3]);
+        detector.update(4, &0, &0);
+        assert_eq!(detector.hash, RepeatDetector::new(&[1, 0, 3, 0, 0]).hash);
+        detector.update(1, &0, &7);
+        assert_eq!(detector.hash, RepeatDetector::new(&[1, 7, 3]).hash);
+    }
+
+    #[test]
+    fn test_detects_repeated_state() {
+        // Flips a flag forever: the state repeats every two iterations.
+        let program = assemble(
+            "loop: eq [flag], #0, [flag]
+                   jz #0, #loop
+             flag: data 0",
+        )
+        .unwrap();
+        let mut machine = Machine::new(&program);
+        machine.detect_repeats();
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Repeats);
+        assert!(machine.steps() < 20);
+    }
+
+    #[test]
+    fn test_counting_loop_halts() {
+        // Every iteration changes memory, so no state repeats before it halts.
+        let program = assemble(
+            "loop: add [n], #1, [n]
+                   lt [n], #1000, [flag]
+                   jnz [flag], #loop
+                   out [n]
+                   hlt
+             n:    data 0
+             flag: data 0",
+        )
+        .unwrap();
+        let mut machine = Machine::new(&program);
+        machine.detect_repeats();
+        machine.run().unwrap();
+        assert_eq!(machine.take_output(), vec![1000]);
+        assert_eq!(run_program(&program, &[]), Ok(vec![1000]));
+    }
+
+    #[test]
+    fn test_input_is_not_a_repeat() {
+        // Echoes input forever; it only ever waits, it never repeats on its own.
+        let mut machine = Machine::new(&[3, 7, 4, 7, 1105, 1, 0, 0]);
+        machine.detect_repeats();
+        for value in [5, 5, 5].iter() {
+            machine.push_input(*value);
+            assert_eq!(machine.resume(), Ok(State::Output(*value)));
+        }
+        assert_eq!(machine.resume(), Ok(State::NeedsInput));
+    }
+}
@@ -0,0 +1,231 @@
+//! Saving a machine's full state to a file and restoring it later.
+//!
+//! Snapshots are plain text, one field per line, with lists written
+//! comma-separated like program files:
+//!
+//! ```text
+//! intcode snapshot 1
+//! index 14
+//! relative-base 0
+//! halted false
+//! steps 4
+//! overflow-checks false
+//! input 5,6
+//! output 7
+//! memory 3,100,1006,100,14,1002,100,2,101,4,101,1105,1,0,99
+//! ```
+//!
+//! A machine with a step budget also saves it, as `budget <step>`, the step
+//! count at which it stops. Fields missing from a file, such as `steps` in
+//! files saved before it was added, take their value in a fresh machine.
+
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::word::Word;
+
+const HEADER: &str = "intcode snapshot 1";
+
+/// Everything needed to continue a run exactly where it stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot<W = i64> {
+    pub memory: Vec<W>,
+    pub index: i64,
+    pub relative_base: i64,
+    pub halted: bool,
+    /// Instructions executed so far.
+    pub steps: u64,
+    /// Whether arithmetic fails on overflow instead of wrapping.
+    pub overflow_checks: bool,
+    /// Step count at which the machine stops, if limited.
+    pub budget: Option<u64>,
+    /// Input queued but not read yet.
+    pub input: Vec<W>,
+    /// Output produced but not taken yet.
+    pub output: Vec<W>,
+}
+
+fn join<W: Word>(values: &[W]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn invalid(line: usize, message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message))
+}
+
+impl<W: Word> Snapshot<W> {
+    pub fn write_to<T: Write>(&self, mut writer: T) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "index {}", self.index)?;
+        writeln!(writer, "relative-base {}", self.relative_base)?;
+        writeln!(writer, "halted {}", self.halted)?;
+        writeln!(writer, "steps {}", self.steps)?;
+        writeln!(writer, "overflow-checks {}", self.overflow_checks)?;
+        if let Some(budget) = self.budget {
+            writeln!(writer, "budget {}", budget)?;
+        }
+        writeln!(writer, "input {}", join(&self.input))?;
+        writeln!(writer, "output {}", join(&self.output))?;
+        writeln!(writer, "memory {}", join(&self.memory))?;
+        writer.flush()
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Snapshot<W>> {
+        let mut lines = reader.lines();
+        if lines.next().transpose()?.as_deref().map(str::trim) != Some(HEADER) {
+            return Err(invalid(1, format!("expected {:?}", HEADER)));
+        }
+        let mut snapshot = Snapshot {
+            memory: Vec::new(),
+            index: 0,
+            relative_base: 0,
+            halted: false,
+            steps: 0,
+            overflow_checks: false,
+            budget: None,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        for (number, line) in lines.enumerate() {
+            let number = number + 2;
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(pos) => (&line[..pos], line[pos + 1..].trim()),
+                None => (line, ""),
+            };
+            let invalid_value = || invalid(number, format!("invalid {} {:?}", key, value));
+            let number_value = || value.parse::<i64>().map_err(|_| invalid_value());
+            let list = || -> io::Result<Vec<W>> {
+                if value.is_empty() {
+                    return Ok(Vec::new());
+                }
+                value
+                    .split(',')
+                    .map(|v| v.trim().parse::<W>().map_err(|_| invalid(number, format!("invalid {} value {:?}", key, v))))
+                    .collect()
+            };
+            match key {
+                "index" => snapshot.index = number_value()?,
+                "relative-base" => snapshot.relative_base = number_value()?,
+                "halted" => snapshot.halted = value.parse().map_err(|_| invalid_value())?,
+                "steps" => snapshot.steps = value.parse().map_err(|_| invalid_value())?,
+                "overflow-checks" => snapshot.overflow_checks = value.parse().map_err(|_| invalid_value())?,
+                "budget" => snapshot.budget = Some(value.parse().map_err(|_| invalid_value())?),
+                "input" => snapshot.input = list()?,
+                "output" => snapshot.output = list()?,
+                "memory" => snapshot.memory = list()?,
+                _ => return Err(invalid(number, format!("unknown field {:?}", key))),
+            }
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(io::BufWriter::new(fs::File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshot<W>> {
+        Snapshot::read_from(BufReader::new(fs::File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::machine::{Machine, State};
+
+    // Doubles every input until it reads 0.
+    const DOUBLER: [i64; 15] = [3, 100, 1006, 100, 14, 1002, 100, 2, 101, 4, 101, 1105, 1, 0, 99];
+
+    #[test]
+    fn test_round_trip() {
+        let mut machine = Machine::new(&[109, 7, 3, 20, 104, 1, 99]);
+        machine.push_input(5);
+        machine.push_input(6);
+        machine.enable_overflow_checks();
+        machine.step().unwrap();
+        machine.step().unwrap();
+        machine.step().unwrap();
+        let snapshot = machine.snapshot();
+        assert_eq!((snapshot.steps, snapshot.overflow_checks), (3, true));
+        assert_eq!(snapshot.index, 6);
+        assert_eq!(snapshot.relative_base, 7);
+        assert_eq!(snapshot.input, vec![6]);
+        assert_eq!(snapshot.output, vec![1]);
+
+        let mut text = Vec::new();
+        snapshot.write_to(&mut text).unwrap();
+        assert_eq!(Snapshot::read_from(text.as_slice()).unwrap(), snapshot);
+        let restored = Machine::from_snapshot(snapshot);
+        assert_eq!(restored.steps(), 3);
+        assert!(restored.snapshot().overflow_checks);
+    }
+
+    #[test]
+    fn test_fields_default() {
+        let snapshot = Snapshot::<i64>::read_from("intcode snapshot 1\nindex 2\nmemory 99".as_bytes()).unwrap();
+        assert_eq!((snapshot.index, snapshot.steps, snapshot.overflow_checks), (2, 0, false));
+        assert_eq!(snapshot.budget, None);
+    }
+
+    #[test]
+    fn test_restore_keeps_budget() {
+        let mut machine = Machine::new(&[1105, 1, 0]);
+        machine.set_budget(Some(5));
+        machine.step().unwrap();
+        machine.step().unwrap();
+        let mut text = Vec::new();
+        machine.snapshot().write_to(&mut text).unwrap();
+        assert!(String::from_utf8(text.clone()).unwrap().contains("\nbudget 5\n"));
+        let mut restored = Machine::from_snapshot(Snapshot::<i64>::read_from(text.as_slice()).unwrap());
+        let error = restored.run().unwrap_err();
+        assert_eq!((error.kind, restored.steps()), (ErrorKind::BudgetExhausted, 5));
+    }
+
+    #[test]
+    fn test_restore_keeps_overflow_checks() {
+        let mut machine = Machine::new(&[1002, 7, 2, 7, 1105, 1, 0, 1 << 61]);
+        machine.enable_overflow_checks();
+        machine.step().unwrap();
+        let mut restored = Machine::from_snapshot(machine.snapshot());
+        restored.set_budget(Some(10));
+        assert_eq!(restored.run().unwrap_err().kind, ErrorKind::Overflow);
+    }
+
+    #[test]
+    fn test_restore_continues_the_run() {
+        let mut machine = Machine::new(&DOUBLER);
+        machine.push_input(3);
+        assert_eq!(machine.resume(), Ok(State::Output(6)));
+        machine.push_input(4);
+
+        let path = std::env::temp_dir().join(format!("intcode-snapshot-{}.txt", std::process::id()));
+        machine.snapshot().save(&path).unwrap();
+        let mut restored = Machine::from_snapshot(Snapshot::load(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        for machine in [&mut machine, &mut restored].iter_mut() {
+            assert_eq!(machine.resume(), Ok(State::Output(8)));
+            machine.push_input(0);
+            assert_eq!(machine.resume(), Ok(State::Halted));
+        }
+        assert_eq!(restored.memory(), machine.memory());
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        let error = |text: &str| Snapshot::<i64>::read_from(text.as_bytes()).unwrap_err().to_string();
+        assert_eq!(error("memory 1,2"), "line 1: expected \"intcode snapshot 1\"");
+        assert_eq!(error("intcode snapshot 1\nindex x"), "line 2: invalid index \"x\"");
+        assert_eq!(error("intcode snapshot 1\n\nmemory 1,,2"), "line 3: invalid memory value \"\"");
+        assert_eq!(error("intcode snapshot 1\nip 4"), "line 2: unknown field \"ip\"");
+        assert_eq!(error("intcode snapshot 1\nsteps -1"), "line 2: invalid steps \"-1\"");
+    }
+}
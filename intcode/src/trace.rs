@@ -0,0 +1,201 @@
+//! Execution traces with one JSON record per instruction (JSON Lines).
+//!
+//! Every record has the same keys in the same order and `null` for what an
+//! instruction does not have, so two traces of the same program can be
+//! compared line by line:
+//!
+//! ```text
+//! {"step":0,"ip":0,"opcode":1,"operands":[{"mode":"position","word":9,"address":9,"value":30},{"mode":"immediate","word":4,"address":null,"value":4},{"mode":"position","word":3,"address":3,"value":null}],"writes":[{"address":3,"old":0,"new":34}],"relative_base":0,"input":null,"output":null}
+//! ```
+
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::machine::Mode;
+use crate::word::Word;
+
+/// An operand as the instruction saw it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceOperand<W = i64> {
+    pub mode: Mode,
+    /// The operand word as it appears in the instruction.
+    pub word: W,
+    /// The address it refers to, `None` in immediate mode.
+    pub address: Option<i64>,
+    /// The value read, `None` for the operand an instruction writes to.
+    pub value: Option<W>,
+}
+
+/// One executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord<W = i64> {
+    /// Instructions executed before this one.
+    pub step: u64,
+    pub ip: i64,
+    pub opcode: i64,
+    pub operands: Vec<TraceOperand<W>>,
+    /// Addresses written, in order, with their old and new values.
+    pub writes: Vec<(i64, W, W)>,
+    /// The relative base the operands were resolved with.
+    pub relative_base: i64,
+    pub input: Option<W>,
+    pub output: Option<W>,
+}
+
+struct Json<'a, T>(&'a Option<T>);
+
+impl<T: fmt::Display> fmt::Display for Json<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{}", value),
+            None => write!(f, "null"),
+        }
+    }
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Position => "position",
+        Mode::Immediate => "immediate",
+        Mode::Relative => "relative",
+    }
+}
+
+impl<W: Word> fmt::Display for TraceOperand<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{{\"mode\":\"{}\",\"word\":{},\"address\":{},\"value\":{}}}",
+            mode_name(self.mode),
+            self.word,
+            Json(&self.address),
+            Json(&self.value)
+        )
+    }
+}
+
+/// The record as a single line of JSON.
+impl<W: Word> fmt::Display for TraceRecord<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{\"step\":{},\"ip\":{},\"opcode\":{},\"operands\":[", self.step, self.ip, self.opcode)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", operand)?;
+        }
+        write!(f, "],\"writes\":[")?;
+        for (i, (address, old, new)) in self.writes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{{\"address\":{},\"old\":{},\"new\":{}}}", address, old, new)?;
+        }
+        write!(
+            f,
+            "],\"relative_base\":{},\"input\":{},\"output\":{}}}",
+            self.relative_base,
+            Json(&self.input),
+            Json(&self.output)
+        )
+    }
+}
+
+/// Where two traces first differ: the 1-based line number and both lines,
+/// `None` for a trace that ended first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub line: usize,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// Compares two traces line by line. Returns `None` if they are the same.
+pub fn first_divergence<A: BufRead, B: BufRead>(left: A, right: B) -> io::Result<Option<Divergence>> {
+    let (mut left, mut right) = (left.lines(), right.lines());
+    let mut line = 0;
+    loop {
+        line += 1;
+        let (a, b) = (left.next().transpose()?, right.next().transpose()?);
+        if a.is_none() && b.is_none() {
+            return Ok(None);
+        }
+        if a != b {
+            return Ok(Some(Divergence { line, left: a, right: b }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::machine::Machine;
+
+    fn trace(program: &[i64], input: &[i64]) -> Vec<String> {
+        let mut machine = Machine::new(program);
+        machine.enable_tracing();
+        for value in input {
+            machine.push_input(*value);
+        }
+        machine.run().unwrap();
+        machine.take_trace().iter().map(|record| record.to_string()).collect()
+    }
+
+    #[test]
+    fn test_records() {
+        let program = assemble(
+            "arb #20
+             in [rb-1]
+             mul [rb-1], #3, [x]
+             out [x]
+             hlt
+             x: data 0",
+        )
+        .unwrap();
+        let lines = trace(&program, &[7]);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            "{\"step\":0,\"ip\":0,\"opcode\":9,\"operands\":[{\"mode\":\"immediate\",\"word\":20,\"address\":null,\"value\":20}],\
+             \"writes\":[],\"relative_base\":0,\"input\":null,\"output\":null}"
+        );
+        assert_eq!(
+            lines[1],
+            "{\"step\":1,\"ip\":2,\"opcode\":3,\"operands\":[{\"mode\":\"relative\",\"word\":-1,\"address\":19,\"value\":null}],\
+             \"writes\":[{\"address\":19,\"old\":0,\"new\":7}],\"relative_base\":20,\"input\":7,\"output\":null}"
+        );
+        assert_eq!(
+            lines[2],
+            "{\"step\":2,\"ip\":4,\"opcode\":2,\"operands\":[{\"mode\":\"relative\",\"word\":-1,\"address\":19,\"value\":7},\
+             {\"mode\":\"immediate\",\"word\":3,\"address\":null,\"value\":3},{\"mode\":\"position\",\"word\":11,\"address\":11,\"value\":null}],\
+             \"writes\":[{\"address\":11,\"old\":0,\"new\":21}],\"relative_base\":20,\"input\":null,\"output\":null}"
+        );
+        assert!(lines[3].ends_with("\"input\":null,\"output\":21}"));
+        assert!(lines[4].starts_with("{\"step\":4,\"ip\":10,\"opcode\":99,\"operands\":[],"));
+    }
+
+    #[test]
+    fn test_trace_is_taken() {
+        let mut machine = Machine::new(&[104, 1, 99]);
+        machine.step().unwrap();
+        assert!(machine.take_trace().is_empty());
+        machine.enable_tracing();
+        machine.run().unwrap();
+        assert_eq!(machine.take_trace().len(), 1);
+        assert!(machine.take_trace().is_empty());
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let program = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let (seven, eight) = (trace(&program, &[7]).join("\n"), trace(&program, &[8]).join("\n"));
+        assert_eq!(first_divergence(seven.as_bytes(), seven.as_bytes()).unwrap(), None);
+        let divergence = first_divergence(seven.as_bytes(), eight.as_bytes()).unwrap().unwrap();
+        assert_eq!(divergence.line, 1);
+        assert!(divergence.left.unwrap().contains("\"input\":7"));
+        let shorter = seven.lines().take(2).collect::<Vec<_>>().join("\n");
+        let divergence = first_divergence(seven.as_bytes(), shorter.as_bytes()).unwrap().unwrap();
+        assert_eq!((divergence.line, divergence.right), (3, None));
+    }
+}
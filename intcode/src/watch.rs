@@ -0,0 +1,236 @@
+//! Write watchpoints and detection of self-modifying code.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::asm::MNEMONICS;
+use crate::disasm::decode;
+use crate::word::Word;
+
+/// Number of words in the standard instruction starting with `word`,
+/// counting unknown opcodes as a single word.
+pub(crate) fn instruction_size(word: i64) -> usize {
+    MNEMONICS
+        .iter()
+        .find(|(_, code, _)| *code == word % 100)
+        .map_or(1, |(_, _, count)| 1 + count)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The address is inside a watched range.
+    Watchpoint,
+    /// The address belongs to an instruction that has already run.
+    Executed,
+    /// The address belongs to the instruction that runs next.
+    Upcoming,
+}
+
+/// A write that hit a watchpoint or modified code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteHit<W = i64> {
+    /// Address of the writing instruction.
+    pub index: i64,
+    /// The writing instruction's words, as they were before it ran.
+    pub instruction: Vec<W>,
+    pub address: i64,
+    pub old: W,
+    pub new: W,
+    pub reason: Reason,
+}
+
+impl<W: Word> fmt::Display for WriteHit<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Option<Vec<i64>> = self.instruction.iter().map(Word::to_i64).collect();
+        let text = match words.as_deref().and_then(|words| decode(words, 0)) {
+            Some(decoded) => decoded.to_string(),
+            None => format!("{:?}", self.instruction),
+        };
+        write!(f, "{}: {} wrote {} to {} (was {}), ", self.index, text, self.new, self.address, self.old)?;
+        match self.reason {
+            Reason::Watchpoint => write!(f, "watchpoint"),
+            Reason::Executed => write!(f, "overwrote code that already ran"),
+            Reason::Upcoming => write!(f, "overwrote the next instruction"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Watch<W> {
+    ranges: Vec<Range<i64>>,
+    self_modification: bool,
+    /// Words that belong to instructions that have run.
+    executed: Vec<bool>,
+    hits: Vec<WriteHit<W>>,
+}
+
+impl<W> Default for Watch<W> {
+    fn default() -> Watch<W> {
+        Watch {
+            ranges: Vec::new(),
+            self_modification: false,
+            executed: Vec::new(),
+            hits: Vec::new(),
+        }
+    }
+}
+
+impl<W: Word> Watch<W> {
+    pub(crate) fn add(&mut self, range: Range<i64>) {
+        self.ranges.push(range);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    pub(crate) fn ranges(&self) -> &[Range<i64>] {
+        &self.ranges
+    }
+
+    pub(crate) fn detect_self_modification(&mut self) {
+        self.self_modification = true;
+    }
+
+    pub(crate) fn detects_self_modification(&self) -> bool {
+        self.self_modification
+    }
+
+    /// Records that the instruction `words` at `index` ran and, for each of
+    /// its `writes` (address, old value, new value) that is interesting,
+    /// why. `upcoming` holds the addresses of the instruction that runs next.
+    pub(crate) fn record(&mut self, index: i64, words: &[W], upcoming: Range<i64>, writes: &[(i64, W, W)]) {
+        if self.self_modification && index >= 0 {
+            let end = index as usize + words.len();
+            if end > self.executed.len() {
+                self.executed.resize(end, false);
+            }
+            for executed in &mut self.executed[index as usize..end] {
+                *executed = true;
+            }
+        }
+        for (address, old, new) in writes {
+            let mut reasons = Vec::new();
+            if self.ranges.iter().any(|range| range.contains(address)) {
+                reasons.push(Reason::Watchpoint);
+            }
+            if self.self_modification {
+                if *self.executed.get(*address as usize).unwrap_or(&false) {
+                    reasons.push(Reason::Executed);
+                } else if upcoming.contains(address) {
+                    reasons.push(Reason::Upcoming);
+                }
+            }
+            for reason in reasons {
+                self.hits.push(WriteHit {
+                    index,
+                    instruction: words.to_vec(),
+                    address: *address,
+                    old: old.clone(),
+                    new: new.clone(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    pub(crate) fn take_hits(&mut self) -> Vec<WriteHit<W>> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::extension::Action;
+    use crate::machine::Machine;
+
+    #[test]
+    fn test_watchpoints() {
+        let program = assemble(
+            "in [a]
+             add [a], #1, [b]
+             out [b]
+             hlt
+             a: data 0
+             b: data 0",
+        )
+        .unwrap();
+        let mut machine = Machine::new(&program);
+        machine.watch_writes(10..11);
+        machine.push_input(4);
+        machine.step().unwrap();
+        assert!(machine.take_write_hits().is_empty());
+        machine.step().unwrap();
+        let hits = machine.take_write_hits();
+        assert_eq!(
+            hits,
+            vec![WriteHit {
+                index: 2,
+                instruction: vec![1001, 9, 1, 10],
+                address: 10,
+                old: 0,
+                new: 5,
+                reason: Reason::Watchpoint,
+            }]
+        );
+        assert_eq!(hits[0].to_string(), "2: add [9], #1, [10] wrote 5 to 10 (was 0), watchpoint");
+        machine.clear_watchpoints();
+        machine.run().unwrap();
+        assert!(machine.take_write_hits().is_empty());
+    }
+
+    #[test]
+    fn test_next_instruction_modified() {
+        // day5's example turns the 33 at address 4 into 99 just before running it.
+        let mut machine = Machine::new(&[1002, 4, 3, 4, 33]);
+        machine.detect_self_modification();
+        machine.run().unwrap();
+        let hits = machine.take_write_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].reason, Reason::Upcoming);
+        assert_eq!(hits[0].to_string(), "0: mul [4], #3, [4] wrote 99 to 4 (was 33), overwrote the next instruction");
+    }
+
+    #[test]
+    fn test_executed_code_modified() {
+        let program = assemble(
+            "start: out #7
+                    jnz [flag], #done
+                    add #1, #0, [flag]
+                    add #4, #0, [start]
+                    jz #0, #start
+             done:  hlt
+             flag:  data 0",
+        )
+        .unwrap();
+        let mut machine = Machine::new(&program);
+        machine.detect_self_modification();
+        machine.run().unwrap();
+        let hits = machine.take_write_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].index, hits[0].address, hits[0].reason), (9, 0, Reason::Executed));
+        // Writing data the program only reads is not reported.
+        assert!(hits.iter().all(|hit| hit.address != 17));
+    }
+
+    #[test]
+    fn test_custom_instruction_modified() {
+        // 50 takes two operands; the add before it overwrites the second.
+        let mut machine = Machine::new(&[1101, 7, 0, 6, 50, 0, 0, 99]);
+        machine.register_opcode(50, 2, |_, _| Ok(Action::Continue));
+        machine.detect_self_modification();
+        machine.run().unwrap();
+        let hits = machine.take_write_hits();
+        assert_eq!((hits.len(), hits[0].address, hits[0].reason), (1, 6, Reason::Upcoming));
+    }
+
+    #[test]
+    fn test_instruction_size() {
+        assert_eq!(instruction_size(1002), 4);
+        assert_eq!(instruction_size(3), 2);
+        assert_eq!(instruction_size(99), 1);
+        assert_eq!(instruction_size(42), 1);
+    }
+}
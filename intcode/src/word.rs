@@ -0,0 +1,148 @@
+//! The values a [`Machine`](crate::Machine) keeps in memory.
+//!
+//! Addresses, the instruction pointer and the relative base are always
+//! `i64`; only the words in memory, input and output use the word type.
+//! `i64` words wrap around on overflow unless the machine checks for it
+//! with [`Machine::enable_overflow_checks`](crate::Machine::enable_overflow_checks).
+//! With the `bigint` feature, [`BigInt`](num_bigint::BigInt) words never
+//! overflow.
+
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+pub trait Word: Clone + Default + Eq + Ord + Hash + Debug + Display + FromStr + Send + 'static {
+    fn from_i64(value: i64) -> Self;
+
+    /// The value as an `i64`, or `None` if it does not fit.
+    fn to_i64(&self) -> Option<i64>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `None` if the sum does not fit in the word.
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn wrapping_add(&self, other: &Self) -> Self;
+
+    fn wrapping_mul(&self, other: &Self) -> Self;
+}
+
+impl Word for i64 {
+    fn from_i64(value: i64) -> i64 {
+        value
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn checked_add(&self, other: &i64) -> Option<i64> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &i64) -> Option<i64> {
+        i64::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &i64) -> i64 {
+        i64::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &i64) -> i64 {
+        i64::wrapping_mul(*self, *other)
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        std::convert::TryFrom::try_from(self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, ErrorKind};
+    use crate::machine::Machine;
+
+    // Squares its input twice: 3037000500 squared no longer fits in an i64.
+    const SQUARES: [i64; 14] = [3, 13, 2, 13, 13, 13, 2, 13, 13, 13, 4, 13, 99, 0];
+
+    #[test]
+    fn test_wrapping() {
+        let mut machine = Machine::new(&SQUARES);
+        machine.push_input(3_037_000_500);
+        machine.run().unwrap();
+        assert_eq!(machine.take_output(), vec![3_037_000_500i64.wrapping_mul(3_037_000_500).wrapping_pow(2)]);
+    }
+
+    #[test]
+    fn test_overflow_checks() {
+        let mut machine = Machine::new(&SQUARES);
+        machine.enable_overflow_checks();
+        machine.push_input(3_037_000_500);
+        let error = machine.run().unwrap_err();
+        assert_eq!(error, Error { kind: ErrorKind::Overflow, index: 2, instruction: 2 });
+        assert_eq!(error.to_string(), "arithmetic overflow at 2 (instruction 2)");
+        // Nothing was written, so the machine can be inspected as it was.
+        assert_eq!(machine.read(13), 3_037_000_500);
+
+        let mut machine = Machine::new(&[1101, i64::MAX, 1, 5, 99]);
+        machine.enable_overflow_checks();
+        assert_eq!(machine.run().unwrap_err().kind, ErrorKind::Overflow);
+        // Address arithmetic is always checked.
+        let error = Machine::new(&[109, i64::MAX, 204, 1, 99]).run().unwrap_err();
+        assert_eq!((error.kind, error.index), (ErrorKind::Overflow, 2));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint() {
+        use crate::parse::parse_words;
+        use num_bigint::BigInt;
+
+        let mut machine: Machine<BigInt> = Machine::from_program(&SQUARES);
+        machine.push_input(BigInt::from(3_037_000_500i64));
+        machine.run().unwrap();
+        let expected = BigInt::from(3_037_000_500i64).pow(4);
+        assert_eq!(machine.take_output(), vec![expected.clone()]);
+        assert_eq!(machine.read(13), expected);
+
+        let program: Vec<BigInt> = parse_words("104,123456789012345678901234567890,99").unwrap();
+        let mut machine = Machine::from_words(&program);
+        machine.run().unwrap();
+        assert_eq!(machine.take_output()[0].to_string(), "123456789012345678901234567890");
+
+        // Words too big to be an address cannot be used as one.
+        let error = Machine::from_words(&[BigInt::from(4), program[1].clone(), BigInt::from(99)]).run().unwrap_err();
+        assert_eq!((error.kind, error.index), (ErrorKind::Overflow, 0));
+    }
+}
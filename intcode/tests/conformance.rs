@@ -0,0 +1,295 @@
+//! Runs every way of running a machine, and the interpreter the days used
+//! before the shared crate, against the conformance corpus and against
+//! random programs, and checks that they all agree.
+
+mod legacy;
+
+use std::collections::{HashMap, VecDeque};
+
+use intcode::conformance::{corpus, Case};
+use intcode::{Error, ErrorKind, Machine, Snapshot, State};
+use proptest::prelude::*;
+
+/// Steps any variant may take, so programs that loop forever still end.
+const BUDGET: u64 = 5_000;
+
+/// Everything a run ends with that every variant must agree on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Outcome {
+    output: Vec<i64>,
+    /// Memory without trailing zeros.
+    memory: Vec<i64>,
+    steps: u64,
+    error: Option<Error>,
+}
+
+fn trimmed(mut memory: Vec<i64>) -> Vec<i64> {
+    while memory.last() == Some(&0) {
+        memory.pop();
+    }
+    memory
+}
+
+fn outcome(mut machine: Machine, result: Result<(), Error>) -> Outcome {
+    Outcome {
+        output: machine.take_output(),
+        memory: trimmed(machine.memory()),
+        steps: machine.steps(),
+        error: result.err(),
+    }
+}
+
+fn machine(program: &[i64], input: &[i64]) -> Machine {
+    let mut machine = Machine::new(program);
+    for value in input {
+        machine.push_input(*value);
+    }
+    machine.set_budget(Some(BUDGET));
+    machine
+}
+
+fn plain(program: &[i64], input: &[i64]) -> Outcome {
+    let mut machine = machine(program, input);
+    let result = machine.run();
+    outcome(machine, result)
+}
+
+fn cached(program: &[i64], input: &[i64]) -> Outcome {
+    let mut machine = machine(program, input);
+    machine.enable_cache();
+    let result = machine.run();
+    outcome(machine, result)
+}
+
+fn compiled(program: &[i64], input: &[i64]) -> Outcome {
+    let mut machine = machine(program, input);
+    machine.enable_compilation();
+    let result = machine.run();
+    outcome(machine, result)
+}
+
+/// With everything that takes the slow path through `step` switched on.
+fn observed(program: &[i64], input: &[i64]) -> Outcome {
+    let mut machine = machine(program, input);
+    machine.enable_history(16);
+    machine.enable_profiling();
+    machine.enable_tracing();
+    machine.watch_writes(0..8);
+    machine.detect_self_modification();
+    let result = machine.run();
+    assert_eq!(machine.take_trace().len() as u64, machine.steps());
+    outcome(machine, result)
+}
+
+/// Runs to the end, steps all the way back to the start and runs again.
+fn replayed(program: &[i64], input: &[i64]) -> Outcome {
+    let mut machine = machine(program, input);
+    machine.enable_history(BUDGET as usize);
+    let _ = machine.run();
+    let steps = machine.steps() as usize;
+    assert_eq!(machine.rewind(steps + 1), steps);
+    assert_eq!(trimmed(machine.memory()), trimmed(program.to_vec()));
+    assert_eq!((machine.index(), machine.relative_base(), machine.pending_input()), (0, 0, input.len()));
+    machine.set_budget(Some(BUDGET));
+    let result = machine.run();
+    outcome(machine, result)
+}
+
+/// Hands input over one value at a time through `run_with`.
+fn resumed(program: &[i64], input: &[i64]) -> Outcome {
+    let mut machine = machine(program, &[]);
+    let mut output = Vec::new();
+    let result = match machine.run_with(input.iter().copied().collect::<VecDeque<_>>(), &mut output) {
+        Ok(State::Halted) => Ok(()),
+        // Out of input: let `step` report it the way `run` would.
+        Ok(_) => machine.step(),
+        Err(e) => Err(e),
+    };
+    let mut outcome = outcome(machine, result);
+    outcome.output = output;
+    outcome
+}
+
+/// Stops half way, saves, loads and continues in a new machine.
+fn restored(program: &[i64], input: &[i64]) -> Outcome {
+    restored_with(program, input, false)
+}
+
+/// [`restored`], with overflow checks on if `checks` is set.
+fn restored_with(program: &[i64], input: &[i64], checks: bool) -> Outcome {
+    let mut machine = machine(program, input);
+    if checks {
+        machine.enable_overflow_checks();
+    }
+    machine.set_budget(Some(BUDGET / 2));
+    match machine.run() {
+        Err(Error { kind: ErrorKind::BudgetExhausted, .. }) => {}
+        result => return outcome(machine, result),
+    }
+    let mut saved = Vec::new();
+    machine.snapshot().write_to(&mut saved).unwrap();
+    let mut restored = Machine::from_snapshot(Snapshot::read_from(&saved[..]).unwrap());
+    assert_eq!(restored.steps(), machine.steps());
+    restored.set_budget(Some(BUDGET - machine.steps()));
+    let result = restored.run();
+    outcome(restored, result)
+}
+
+/// Agrees with the others unless it reports [`ErrorKind::Overflow`].
+fn checked(program: &[i64], input: &[i64]) -> Outcome {
+    let mut machine = machine(program, input);
+    machine.enable_overflow_checks();
+    let result = machine.run();
+    outcome(machine, result)
+}
+
+/// Agrees with [`checked`] unless that reports [`ErrorKind::Overflow`].
+#[cfg(feature = "bigint")]
+fn bigint(program: &[i64], input: &[i64]) -> Option<Outcome> {
+    use intcode::{BigInt, Word};
+
+    let to_i64 = |words: Vec<BigInt>| words.iter().map(Word::to_i64).collect::<Option<Vec<_>>>();
+    let mut machine: Machine<BigInt> = Machine::from_program(program);
+    for value in input {
+        machine.push_input(BigInt::from(*value));
+    }
+    machine.set_budget(Some(BUDGET));
+    let result = machine.run();
+    Some(Outcome {
+        output: to_i64(machine.take_output())?,
+        memory: trimmed(to_i64(machine.memory())?),
+        steps: machine.steps(),
+        error: result.err(),
+    })
+}
+
+/// The output and memory of the old interpreter, which has no budget and
+/// no errors, so it only runs programs that halt.
+fn legacy(program: &[i64], input: &[i64]) -> (Vec<i64>, Vec<i64>) {
+    let mut memory: HashMap<i64, i64> = program.iter().enumerate().map(|(i, v)| (i as i64, *v)).collect();
+    let output = legacy::run_program(&mut memory, input);
+    let mut flat = vec![0; memory.keys().max().map_or(0, |last| *last as usize + 1)];
+    for (address, value) in memory {
+        flat[address as usize] = value;
+    }
+    (output, trimmed(flat))
+}
+
+type Variant = fn(&[i64], &[i64]) -> Outcome;
+
+/// The variants that must agree on every program.
+const VARIANTS: [(&str, Variant); 7] = [
+    ("plain", plain),
+    ("cached", cached),
+    ("compiled", compiled),
+    ("observed", observed),
+    ("replayed", replayed),
+    ("resumed", resumed),
+    ("restored", restored),
+];
+
+/// Runs `program` every way and returns the plain outcome, checking the
+/// others against it.
+fn run_all(program: &[i64], input: &[i64]) -> Outcome {
+    let expected = plain(program, input);
+    for (name, variant) in VARIANTS.iter() {
+        assert_eq!(variant(program, input), expected, "{} differs on {:?} with input {:?}", name, program, input);
+    }
+    let checked = checked(program, input);
+    assert_eq!(restored_with(program, input, true), checked, "checked and restored differs on {:?}", program);
+    if matches!(checked.error, Some(Error { kind: ErrorKind::Overflow, .. })) {
+        return expected;
+    }
+    assert_eq!(checked, expected, "checked differs on {:?} with input {:?}", program, input);
+    #[cfg(feature = "bigint")]
+    assert_eq!(bigint(program, input).as_ref(), Some(&expected), "bigint differs on {:?}", program);
+    if expected.error.is_none() {
+        let (output, memory) = legacy(program, input);
+        assert_eq!((&output, &memory), (&expected.output, &expected.memory), "legacy differs on {:?}", program);
+    }
+    expected
+}
+
+#[test]
+fn test_corpus() {
+    for Case { name, program, input, output, memory } in corpus() {
+        let outcome = run_all(&program, &input);
+        assert_eq!(outcome.error, None, "{}", name);
+        assert_eq!(outcome.output, output, "{}", name);
+        assert_eq!(outcome.memory, memory, "{}", name);
+    }
+}
+
+/// Operand counts of opcodes 1 to 9, and which operand each writes to.
+const SHAPES: [(i64, usize, Option<usize>); 9] = [
+    (1, 3, Some(2)),
+    (2, 3, Some(2)),
+    (3, 1, Some(0)),
+    (4, 1, None),
+    (5, 2, None),
+    (6, 2, None),
+    (7, 3, Some(2)),
+    (8, 3, Some(2)),
+    (9, 1, None),
+];
+
+/// Words after the code for the program to work on.
+const DATA: usize = 8;
+
+/// An instruction drawn at random: which opcode, the mode of each operand
+/// and a seed each operand is made from.
+type Draw = (usize, [u8; 3], [i64; 3]);
+
+/// Turns random draws into a program that mostly makes sense: operands
+/// point into the program, jumps go to the start of an instruction, the
+/// code ends with a halt and half the data are jump targets. Writes may still land on code and the
+/// relative base may wander off, which the variants must agree on too.
+fn program(draws: &[Draw], data: &[i64]) -> Vec<i64> {
+    let mut starts = vec![0];
+    for (shape, _, _) in draws {
+        starts.push(starts.last().unwrap() + 1 + SHAPES[*shape].1 as i64);
+    }
+    let size = starts.last().unwrap() + 1 + DATA as i64;
+    let mut program = Vec::new();
+    for (shape, modes, seeds) in draws {
+        let (code, operands, write) = SHAPES[*shape];
+        let mut word = code;
+        let mut words = Vec::new();
+        for i in 0..operands {
+            let (mut mode, seed) = (modes[i] as i64, seeds[i]);
+            if write == Some(i) && mode == 1 {
+                mode = 0;
+            }
+            words.push(match mode {
+                // Mostly data, sometimes code.
+                0 if seed % 4 == 0 => seed.rem_euclid(size),
+                0 => size - 1 - seed.rem_euclid(DATA as i64),
+                1 if (code == 5 || code == 6) && i == 1 => starts[seed.rem_euclid(starts.len() as i64) as usize],
+                1 if code == 9 => seed % 4,
+                1 if seed % 16 == 0 => seed,
+                1 => seed % 100,
+                _ => seed.rem_euclid(size),
+            });
+            word += mode * 10i64.pow(i as u32 + 2);
+        }
+        program.push(word);
+        program.extend(words);
+    }
+    program.push(99);
+    // Every other word of data is somewhere to jump to.
+    for (i, word) in data.iter().enumerate() {
+        program.push(if i % 2 == 0 { starts[word.rem_euclid(starts.len() as i64) as usize] } else { *word });
+    }
+    program
+}
+
+proptest! {
+    #[test]
+    fn test_variants_agree(
+        draws in prop::collection::vec((0..9usize, [0..3u8, 0..3u8, 0..3u8], any::<[i64; 3]>()), 1..16),
+        data in prop::collection::vec(-20..20i64, DATA),
+        input in prop::collection::vec(-1000..1000i64, 0..4),
+    ) {
+        run_all(&program(&draws, &data), &input);
+    }
+}
@@ -0,0 +1,91 @@
+//! The interpreter from day9 before the shared crate: `HashMap` memory and
+//! a `HashMap` of modes decoded for every instruction. Kept as a reference
+//! for the benchmark and the conformance tests.
+
+use std::collections::HashMap;
+
+enum Mode {
+    Position,
+    Intermediate,
+    Relative,
+}
+
+fn int_to_mode(mode: i64) -> Mode {
+    match mode {
+        0 => Mode::Position,
+        1 => Mode::Intermediate,
+        _ => Mode::Relative,
+    }
+}
+
+fn parse_instruction(instruction: i64) -> (i64, HashMap<i64, Mode>) {
+    let mut modes = HashMap::new();
+    modes.insert(1, int_to_mode(instruction % 1000 / 100));
+    modes.insert(2, int_to_mode(instruction % 10000 / 1000));
+    modes.insert(3, int_to_mode(instruction / 10000));
+    (instruction % 100, modes)
+}
+
+fn get_index(idx: i64, mode: &Mode, relative_base: i64, program: &HashMap<i64, i64>) -> i64 {
+    let pnt = *program.get(&idx).unwrap_or(&0);
+    match mode {
+        Mode::Position => pnt,
+        Mode::Intermediate => idx,
+        Mode::Relative => pnt + relative_base,
+    }
+}
+
+fn get_value(idx: i64, mode: &Mode, relative_base: i64, program: &HashMap<i64, i64>) -> i64 {
+    *program.get(&get_index(idx, mode, relative_base, program)).unwrap_or(&0)
+}
+
+/// Runs until the program halts, hits an unknown opcode or reads past the
+/// end of `inputs`, and returns its output.
+pub fn run_program(program: &mut HashMap<i64, i64>, inputs: &[i64]) -> Vec<i64> {
+    let mut inputs = inputs.iter();
+    let mut index = 0;
+    let mut relative_base = 0;
+    let mut output = Vec::new();
+    loop {
+        let (code, modes) = parse_instruction(program[&index]);
+        let value = |i: i64| get_value(index + i, &modes[&i], relative_base, program);
+        let target = |i: i64| get_index(index + i, &modes[&i], relative_base, program);
+        match code {
+            1 | 2 | 7 | 8 => {
+                let (a, b, c) = (value(1), value(2), target(3));
+                let result = match code {
+                    1 => a + b,
+                    2 => a * b,
+                    7 => (a < b) as i64,
+                    _ => (a == b) as i64,
+                };
+                program.insert(c, result);
+                index += 4;
+            }
+            3 => {
+                let c = target(1);
+                match inputs.next() {
+                    Some(input) => program.insert(c, *input),
+                    None => return output,
+                };
+                index += 2;
+            }
+            4 => {
+                output.push(value(1));
+                index += 2;
+            }
+            5 | 6 => {
+                if (value(1) != 0) == (code == 5) {
+                    index = value(2);
+                } else {
+                    index += 3;
+                }
+            }
+            9 => {
+                relative_base += value(1);
+                index += 2;
+            }
+            _ => return output,
+        }
+    }
+}